@@ -0,0 +1,150 @@
+use std::env;
+
+use anyhow::Context;
+use chrono::{DateTime, Local, Utc};
+use tracing::info;
+
+use sptfydl::{
+    secrets,
+    spotify::{
+        AccessToken, YTM_DATA_CONFIG_NAME, given_ytm_cookie, oauth_config_name, prompt_ytm_cookie,
+        request_token_and_save, saved_accounts, token_config_name,
+    },
+    ytmusic::auth::check_cookie,
+};
+
+use super::credentials::{SpotifyOauth, given_spotify_oauth, prompt_spotify_oauth};
+use crate::{AuthCommand, CommonArgs, Service};
+
+/// Run an `auth` subcommand.
+pub async fn auth(command: &AuthCommand, args: &CommonArgs) -> anyhow::Result<()> {
+    match command {
+        AuthCommand::Status => {
+            auth_status();
+            Ok(())
+        }
+        AuthCommand::Spotify => {
+            let oauth = match given_spotify_oauth(args.spotify_oauth_file.as_deref())? {
+                Some(oauth) => oauth,
+                None => prompt_spotify_oauth()?,
+            };
+            // also caches the token
+            let token =
+                request_token_and_save(&args.account, &oauth.client_id, &oauth.client_secret)
+                    .await
+                    .context("checking the spotify credentials")?;
+            secrets::save(&oauth, &oauth_config_name(&args.account))?;
+
+            let expiry = token.expires_at().map(format_time).unwrap_or_default();
+            info!(
+                "saved the credentials of the spotify account `{}`, got a token expiring at {expiry}",
+                args.account
+            );
+            Ok(())
+        }
+        AuthCommand::Ytm => {
+            let cookie = match given_ytm_cookie(args.ytm_cookie_file.as_deref())? {
+                Some(cookie) => cookie,
+                None => prompt_ytm_cookie()?,
+            };
+            let account = check_cookie(&cookie)
+                .await
+                .context("checking the ytm cookie")?;
+            secrets::save_str(&cookie, YTM_DATA_CONFIG_NAME)?;
+
+            info!("saved the ytm cookie of `{account}`");
+            Ok(())
+        }
+        AuthCommand::Logout { services } => {
+            let all = services.is_empty();
+            let mut names = Vec::new();
+            if all || services.contains(&Service::Spotify) {
+                names.extend([
+                    oauth_config_name(&args.account),
+                    token_config_name(&args.account),
+                ]);
+            }
+            if all || services.contains(&Service::Ytm) {
+                names.push(YTM_DATA_CONFIG_NAME.to_string());
+            }
+
+            for name in names {
+                if secrets::remove(&name).with_context(|| format!("removing {name}"))? {
+                    info!("removed {name}");
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Print which credentials are saved, or given with env vars.
+fn auth_status() {
+    let from_env = |var: &str| env::var(var).is_ok();
+
+    if from_env("SPTFYDL_SPOTIFY_CLIENT_ID") && from_env("SPTFYDL_SPOTIFY_CLIENT_SECRET") {
+        let id = env::var("SPTFYDL_SPOTIFY_CLIENT_ID").unwrap_or_default();
+        println!("spotify client id: {} (from env)", mask(&id));
+    }
+
+    let accounts = match saved_accounts() {
+        Ok(accounts) => accounts,
+        Err(err) => {
+            println!("spotify accounts: {err:#}");
+            Vec::new()
+        }
+    };
+    if accounts.is_empty() {
+        println!("spotify accounts: none saved");
+    }
+    for account in accounts {
+        println!("spotify account `{account}`:");
+        match secrets::load::<SpotifyOauth>(&oauth_config_name(&account)) {
+            Ok(Some(oauth)) => println!("  client id: {}", mask(&oauth.client_id)),
+            Ok(None) => println!("  client id: not saved"),
+            Err(err) => println!("  client id: {err:#}"),
+        }
+
+        match secrets::load::<AccessToken>(&token_config_name(&account)) {
+            Ok(Some(token)) => {
+                let state = if token.expired() {
+                    "expired"
+                } else {
+                    "expires"
+                };
+                let expiry = token
+                    .expires_at()
+                    .map_or_else(|| "unknown".to_string(), format_time);
+                println!("  token: {state} at {expiry}");
+            }
+            Ok(None) => println!("  token: none"),
+            Err(err) => println!("  token: {err:#}"),
+        }
+    }
+
+    if from_env("SPTFYDL_YTM_COOKIE") {
+        println!("ytm cookie: from env");
+    } else {
+        match secrets::saved_at(YTM_DATA_CONFIG_NAME) {
+            Some(saved) => println!("ytm cookie: saved at {}", format_time(saved.into())),
+            None => println!("ytm cookie: not saved, searching anonymously"),
+        }
+    }
+}
+
+/// Show only the start and end of `secret`.
+fn mask(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() <= 8 {
+        return "*".repeat(chars.len());
+    }
+    let start: String = chars[..4].iter().collect();
+    let end: String = chars[chars.len() - 4..].iter().collect();
+    format!("{start}{}{end}", "*".repeat(chars.len() - 8))
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
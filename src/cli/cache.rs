@@ -0,0 +1,61 @@
+use std::{fs, io, path::Path};
+
+use anyhow::Context;
+use indicatif::HumanBytes;
+use tracing::info;
+
+use sptfydl::{secrets, tag::cover::covers_dir};
+
+use crate::{Cache, CacheCommand};
+
+/// Run a `cache` subcommand.
+pub fn cache(command: &CacheCommand) -> anyhow::Result<()> {
+    match command {
+        CacheCommand::Info => {
+            let dir = covers_dir();
+            let (count, size) = dir_size(&dir).context("reading the covers cache")?;
+            println!(
+                "covers: {count} ({}) in {}",
+                HumanBytes(size),
+                dir.display()
+            );
+            let tokens = secrets::find(SPOTIFY_TOKEN_PREFIX).context("reading the config dir")?;
+            println!("spotify tokens: {}", tokens.len());
+            Ok(())
+        }
+        CacheCommand::Clear { caches } => {
+            let all = caches.is_empty();
+            if all || caches.contains(&Cache::Covers) {
+                let dir = covers_dir();
+                let (count, _) = dir_size(&dir).context("reading the covers cache")?;
+                fs::remove_dir_all(&dir).with_context(|| format!("removing {}", dir.display()))?;
+                info!("removed {count} cached covers");
+            }
+            if all || caches.contains(&Cache::Tokens) {
+                for name in secrets::find(SPOTIFY_TOKEN_PREFIX).context("reading the config dir")? {
+                    if secrets::remove(&name).with_context(|| format!("removing {name}"))? {
+                        info!("removed {name}");
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The secret files spotify tokens are cached in start with this, see [`token_config_name`].
+const SPOTIFY_TOKEN_PREFIX: &str = "spotify_token";
+
+/// The number and total size of the files in `dir`.
+fn dir_size(dir: &Path) -> io::Result<(usize, u64)> {
+    let mut count = 0;
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let metadata = entry?.metadata()?;
+        if metadata.is_file() {
+            count += 1;
+            size += metadata.len();
+        }
+    }
+    Ok((count, size))
+}
@@ -0,0 +1,384 @@
+use std::{collections::HashMap, env, ffi::OsString, io};
+
+use anyhow::{Context, anyhow};
+use clap::{
+    ArgAction, ArgMatches, CommandFactory, FromArgMatches, error::ErrorKind, parser::ValueSource,
+};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+
+use sptfydl::{load_str, tag::TagConfig};
+
+use crate::{Args, Command, DownloadArgs};
+
+const CONFIG_NAME: &str = "config.yaml";
+
+/// The args after `--`, which are positional, so they can't be defaulted like options.
+const YTDLP_ARGS: &str = "ytdlp_args";
+
+/// Optional settings from the config dir, overridden by env vars and cli args.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    tagging: TagConfig,
+    /// the network options, grouped
+    network: Mapping,
+    profiles: HashMap<String, Profile>,
+    /// cli options by their name in snake case, like `download_retries: 3`
+    #[serde(flatten)]
+    options: Mapping,
+}
+
+/// Options that override the rest of the config when chosen with `--profile`.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Profile {
+    /// replaces the config's `tagging` section
+    tagging: Option<TagConfig>,
+    /// overrides options of the config's `network` section
+    network: Mapping,
+    #[serde(flatten)]
+    options: Mapping,
+}
+
+impl Config {
+    /// The options and tagging config of `profile`, on top of the rest of the config.
+    fn resolve(mut self, profile: Option<&str>) -> anyhow::Result<(Mapping, TagConfig)> {
+        self.options.extend(
+            network_section(self.network).context("in the network section of config.yaml")?,
+        );
+        let Some(name) = profile else {
+            return Ok((self.options, self.tagging));
+        };
+        let profile = self.profiles.remove(name).ok_or_else(|| {
+            let mut names: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            anyhow!(
+                "there is no profile `{name}` in config.yaml, expected one of: {}",
+                names.join(", ")
+            )
+        })?;
+
+        self.options.extend(profile.options);
+        self.options
+            .extend(network_section(profile.network).with_context(|| {
+                format!("in the network section of profile `{name}` in config.yaml")
+            })?);
+        Ok((self.options, profile.tagging.unwrap_or(self.tagging)))
+    }
+}
+
+/// The options that can be grouped in a `network` section.
+const NETWORK_OPTIONS: [&str; 6] = [
+    "proxy",
+    "connect_timeout",
+    "read_timeout",
+    "user_agent",
+    "force_ipv4",
+    "force_ipv6",
+];
+
+/// Check that the `network` section only has network options.
+fn network_section(network: Mapping) -> anyhow::Result<Mapping> {
+    for name in network.keys() {
+        if !name
+            .as_str()
+            .is_some_and(|name| NETWORK_OPTIONS.contains(&name))
+        {
+            return Err(anyhow!(
+                "`{}` is not a network option, expected one of: {}",
+                serde_yaml::to_string(name)?.trim(),
+                NETWORK_OPTIONS.join(", ")
+            ));
+        }
+    }
+    Ok(network)
+}
+
+pub fn load_config() -> anyhow::Result<Config> {
+    let config: Config = match load_str(CONFIG_NAME) {
+        Ok(config) => serde_yaml::from_str(&config).context("parsing config.yaml")?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Config::default(),
+        Err(err) => return Err(err).context("reading config.yaml"),
+    };
+    config.tagging.validate().context("checking config.yaml")?;
+    for (name, profile) in &config.profiles {
+        if let Some(tagging) = &profile.tagging {
+            tagging
+                .validate()
+                .with_context(|| format!("checking profile `{name}` in config.yaml"))?;
+        }
+    }
+    Ok(config)
+}
+
+/// Parse the cli args, falling back to `SPTFYDL_*` env vars, then the chosen profile, then the rest of `config`.
+///
+/// Returns the args with the tagging config to use.
+pub fn parse_args(config: Config) -> anyhow::Result<(Args, TagConfig)> {
+    parse_args_from(config, env::args_os()).map_err(|err| match err.downcast::<clap::Error>() {
+        // usage errors and --help are printed by clap
+        Ok(err) => err.exit(),
+        Err(err) => err,
+    })
+}
+
+/// [`parse_args`] with the cli args `args`.
+fn parse_args_from(
+    config: Config,
+    args: impl IntoIterator<Item = impl Into<OsString>>,
+) -> anyhow::Result<(Args, TagConfig)> {
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    // the profile decides the defaults, and the subcommand where they go, so they're found first
+    let (profile, path) = args_command()
+        .ignore_errors(true)
+        .try_get_matches_from(&args)
+        .map(|matches| {
+            (
+                matches.get_one::<String>("profile").cloned(),
+                subcommand_path(&matches),
+            )
+        })
+        .unwrap_or_default();
+    let (options, tagging) = config.resolve(profile.as_deref())?;
+
+    let mut command = args_command();
+    let mut ytdlp_args = Vec::new();
+    for (name, value) in &options {
+        let name = name
+            .as_str()
+            .ok_or_else(|| anyhow!("option names in config.yaml must be strings"))?;
+        let values = config_values(&command, name, value)
+            .with_context(|| format!("option `{name}` in config.yaml"))?;
+        if name == YTDLP_ARGS {
+            ytdlp_args = values;
+        } else {
+            command = set_default(command, &path, name, &values);
+        }
+    }
+
+    let matches = command.try_get_matches_from_mut(&args)?;
+    check_download_options(&mut command, &matches)?;
+    let mut args = Args::from_arg_matches(&matches)?;
+    // the configured args go first, so the ones on the command line win where yt-dlp only keeps the last
+    if let Some(download) = download_args(&mut args) {
+        download.ytdlp_args.splice(0..0, ytdlp_args);
+    }
+    Ok((args, tagging))
+}
+
+/// The download args of the parsed subcommand, if it downloads.
+fn download_args(args: &mut Args) -> Option<&mut DownloadArgs> {
+    match &mut args.command {
+        None => Some(&mut args.download),
+        Some(Command::Download(download)) => Some(download),
+        Some(Command::Sync(sync)) => Some(&mut sync.download),
+        Some(_) => None,
+    }
+}
+
+/// Check that download options aren't given before a subcommand, which would ignore them.
+///
+/// Only the options every subcommand takes (the global ones) can be given before it.
+fn check_download_options(
+    command: &mut clap::Command,
+    matches: &ArgMatches,
+) -> clap::error::Result<()> {
+    let Some((subcommand, _)) = matches.subcommand() else {
+        return Ok(());
+    };
+    let misplaced = command.get_arguments().find(|arg| {
+        !arg.is_global_set()
+            && matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+    });
+    match misplaced {
+        Some(arg) => Err(command.error(
+            ErrorKind::ArgumentConflict,
+            format!(
+                "`{}` can't be given before the `{subcommand}` subcommand, only the options of every subcommand can",
+                arg_name(arg)
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// How `arg` is written on the command line, like `--path` or `<URL>`.
+fn arg_name(arg: &clap::Arg) -> String {
+    match arg.get_long() {
+        Some(long) => format!("--{long}"),
+        None => format!("<{}>", arg.get_id().as_str().to_uppercase()),
+    }
+}
+
+/// The clap command of [`Args`], where every option can also be set with a `SPTFYDL_<OPTION>` env var.
+fn args_command() -> clap::Command {
+    with_env(Args::command())
+}
+
+/// Let every option of `command` and its subcommands be set with a `SPTFYDL_<OPTION>` env var.
+fn with_env(command: clap::Command) -> clap::Command {
+    let subcommands: Vec<String> = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    let mut command = command.mut_args(|arg| {
+        if arg.is_positional() {
+            return arg;
+        }
+        let env = format!("SPTFYDL_{}", arg.get_id().as_str().to_uppercase());
+        arg.env(env).hide_env(true)
+    });
+    for name in subcommands {
+        command = command.mut_subcommand(name, with_env);
+    }
+    command
+}
+
+/// Default the option `name` to `values` where it's parsed: in the subcommand at `path` (the
+/// subcommands given on the command line), or in `command` itself for global options.
+fn set_default(
+    command: clap::Command,
+    path: &[String],
+    name: &str,
+    values: &[String],
+) -> clap::Command {
+    let global = command
+        .get_arguments()
+        .any(|arg| arg.get_id() == name && arg.is_global_set());
+    match path.split_first() {
+        Some((sub, rest)) if !global && command.find_subcommand(sub).is_some() => {
+            command.mut_subcommand(sub, |sub| set_default(sub, rest, name, values))
+        }
+        _ if command.get_arguments().any(|arg| arg.get_id() == name) => {
+            command.mut_arg(name, |arg| arg.default_values(values))
+        }
+        _ => command,
+    }
+}
+
+/// The names of the subcommands in `matches`, outermost first.
+fn subcommand_path(mut matches: &ArgMatches) -> Vec<String> {
+    let mut path = Vec::new();
+    while let Some((name, sub)) = matches.subcommand() {
+        path.push(name.to_string());
+        matches = sub;
+    }
+    path
+}
+
+/// The option `name` of `command` or any of its subcommands.
+fn find_arg<'a>(command: &'a clap::Command, name: &str) -> Option<&'a clap::Arg> {
+    command
+        .get_arguments()
+        .find(|arg| arg.get_id() == name)
+        .or_else(|| {
+            command
+                .get_subcommands()
+                .find_map(|sub| find_arg(sub, name))
+        })
+}
+
+/// Turn the config `value` of the option `name` into default values for its arg in `command`.
+fn config_values(
+    command: &clap::Command,
+    name: &str,
+    value: &Value,
+) -> anyhow::Result<Vec<String>> {
+    let arg = find_arg(command, name)
+        .filter(|arg| (!arg.is_positional() || name == YTDLP_ARGS) && name != "profile")
+        .ok_or_else(|| anyhow!("not an option that can be configured"))?;
+
+    let scalar = |value: &Value| match value {
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::String(s) => Ok(s.clone()),
+        _ => Err(anyhow!("expected a string, number or bool")),
+    };
+    // clap checks the values themselves when parsing
+    match value {
+        Value::Sequence(values) if matches!(arg.get_action(), ArgAction::Append) => {
+            values.iter().map(scalar).collect()
+        }
+        value => Ok(vec![scalar(value)?]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, parse_args_from};
+    use crate::{AuthCommand, CacheCommand, Command, Format};
+
+    #[test]
+    fn global_options_before_subcommands() {
+        let (args, _) =
+            parse_args_from(Config::default(), ["sptfydl", "-v", "cache", "info"]).unwrap();
+        assert_eq!(args.common.verbose, 1);
+        assert!(matches!(
+            args.command,
+            Some(Command::Cache(CacheCommand::Info))
+        ));
+
+        let (args, _) = parse_args_from(
+            Config::default(),
+            ["sptfydl", "--account", "work", "auth", "status", "-v"],
+        )
+        .unwrap();
+        assert_eq!(args.common.account, "work");
+        assert_eq!(args.common.verbose, 1);
+        assert!(matches!(
+            args.command,
+            Some(Command::Auth(AuthCommand::Status))
+        ));
+
+        // download options would be ignored before a subcommand
+        let err = parse_args_from(Config::default(), ["sptfydl", "-P", "dir", "cache", "info"])
+            .unwrap_err();
+        assert!(err.to_string().contains("`--path` can't be given before"));
+    }
+
+    #[test]
+    fn profile_defaults_in_subcommands() {
+        let config = || -> Config {
+            serde_yaml::from_str(
+                "
+                ytdlp_args: [--cookies, cookies.txt]
+                profiles:
+                  phone:
+                    format: opus
+                ",
+            )
+            .unwrap()
+        };
+
+        let (args, _) =
+            parse_args_from(config(), ["sptfydl", "--profile", "phone", "cache", "info"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Cache(CacheCommand::Info))
+        ));
+
+        let (args, _) = parse_args_from(
+            config(),
+            [
+                "sptfydl",
+                "--profile",
+                "phone",
+                "download",
+                "url",
+                "--",
+                "-q",
+            ],
+        )
+        .unwrap();
+        let Some(Command::Download(download)) = args.command else {
+            panic!("expected download, got {:?}", args.command);
+        };
+        assert_eq!(download.format, Format::Opus);
+        assert_eq!(download.ytdlp_args, ["--cookies", "cookies.txt", "-q"]);
+
+        let (args, _) = parse_args_from(config(), ["sptfydl", "url"]).unwrap();
+        assert_eq!(args.download.format, Format::Mp3);
+        assert_eq!(args.download.ytdlp_args, ["--cookies", "cookies.txt"]);
+    }
+}
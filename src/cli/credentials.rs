@@ -0,0 +1,154 @@
+use std::{env, fs, path::Path};
+
+use anyhow::{Context, anyhow};
+use dialoguer::{Input, Password};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use sptfydl::{
+    secrets,
+    spotify::{Tokens, oauth_config_name, saved_accounts, spotify_token},
+};
+
+use crate::CommonArgs;
+
+/// The passphrase for saved secrets, from `SPTFYDL_PASSPHRASE` or `passphrase_file`.
+pub fn passphrase(passphrase_file: Option<&Path>) -> anyhow::Result<Option<String>> {
+    if let Some(passphrase) = env_var("SPTFYDL_PASSPHRASE")? {
+        if passphrase.is_empty() {
+            return Err(anyhow!("SPTFYDL_PASSPHRASE is empty"));
+        }
+        return Ok(Some(passphrase));
+    }
+    let Some(path) = passphrase_file else {
+        return Ok(None);
+    };
+
+    let passphrase = fs::read_to_string(path)
+        .with_context(|| format!("reading the passphrase from {}", path.display()))?;
+    let passphrase = passphrase.trim_end_matches(['\r', '\n']);
+    if passphrase.is_empty() {
+        return Err(anyhow!("the passphrase in {} is empty", path.display()));
+    }
+    Ok(Some(passphrase.to_string()))
+}
+
+/// The value of the env var `name`, if it's set.
+fn env_var(name: &str) -> anyhow::Result<Option<String>> {
+    match env::var(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("reading {name}")),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SpotifyOauth {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+/// The spotify credentials from `SPTFYDL_SPOTIFY_CLIENT_ID` and `SPTFYDL_SPOTIFY_CLIENT_SECRET`, or `oauth_file`,
+/// if either is given. These are never saved.
+pub fn given_spotify_oauth(oauth_file: Option<&Path>) -> anyhow::Result<Option<SpotifyOauth>> {
+    const CLIENT_ID: &str = "SPTFYDL_SPOTIFY_CLIENT_ID";
+    const CLIENT_SECRET: &str = "SPTFYDL_SPOTIFY_CLIENT_SECRET";
+
+    match (env_var(CLIENT_ID)?, env_var(CLIENT_SECRET)?) {
+        (Some(client_id), Some(client_secret)) => {
+            return Ok(Some(SpotifyOauth {
+                client_id,
+                client_secret,
+            }));
+        }
+        (Some(_), None) => return Err(anyhow!("{CLIENT_ID} is set, but {CLIENT_SECRET} is not")),
+        (None, Some(_)) => return Err(anyhow!("{CLIENT_SECRET} is set, but {CLIENT_ID} is not")),
+        (None, None) => {}
+    }
+    let Some(path) = oauth_file else {
+        return Ok(None);
+    };
+
+    let oauth = fs::read_to_string(path)
+        .with_context(|| format!("reading spotify credentials from {}", path.display()))?;
+    serde_yaml::from_str(&oauth).context("parsing the spotify credentials")
+}
+
+/// Get the spotify credentials from `SPTFYDL_SPOTIFY_CLIENT_ID` and `SPTFYDL_SPOTIFY_CLIENT_SECRET`,
+/// `oauth_file`, the saved ones of `account`, or else by prompting, saving them.
+fn get_spotify_oauth(
+    account: &str,
+    oauth_file: Option<&Path>,
+    no_interaction: bool,
+) -> anyhow::Result<SpotifyOauth> {
+    if let Some(oauth) = given_spotify_oauth(oauth_file)? {
+        return Ok(oauth);
+    }
+
+    if let Some(oauth) = secrets::load(&oauth_config_name(account))? {
+        Ok(oauth)
+    } else if no_interaction {
+        Err(anyhow!(
+            "no spotify credentials: set SPTFYDL_SPOTIFY_CLIENT_ID and SPTFYDL_SPOTIFY_CLIENT_SECRET, \
+            or pass --spotify-oauth-file"
+        ))
+    } else {
+        info!("no saved credentials for the spotify account `{account}`, need input.");
+        let oauth = prompt_spotify_oauth()?;
+        secrets::save(&oauth, &oauth_config_name(account))?;
+        Ok(oauth)
+    }
+}
+
+/// The spotify tokens to use: of `--account`, then of the other saved accounts with `--rotate-accounts`.
+pub async fn spotify_tokens(args: &CommonArgs) -> anyhow::Result<Tokens> {
+    let oauth = get_spotify_oauth(
+        &args.account,
+        args.spotify_oauth_file.as_deref(),
+        args.no_interaction,
+    )?;
+    let token = spotify_token(&args.account, &oauth.client_id, &oauth.client_secret).await?;
+    let mut tokens = vec![(args.account.clone(), token)];
+
+    if args.rotate_accounts {
+        for account in saved_accounts()? {
+            if account == args.account {
+                continue;
+            }
+            let token = match secrets::load::<SpotifyOauth>(&oauth_config_name(&account)) {
+                Ok(Some(oauth)) => {
+                    spotify_token(&account, &oauth.client_id, &oauth.client_secret).await
+                }
+                Ok(None) => continue,
+                Err(err) => Err(err),
+            };
+            match token {
+                Ok(token) => tokens.push((account, token)),
+                Err(err) => warn!("not rotating to the spotify account `{account}`: {err:#}"),
+            }
+        }
+        if tokens.len() == 1 {
+            warn!(
+                "--rotate-accounts needs more than one saved spotify account, see `auth spotify --account`"
+            );
+        } else {
+            debug!("rotating between {} spotify accounts", tokens.len());
+        }
+    }
+
+    Ok(Tokens::new(tokens))
+}
+
+pub fn prompt_spotify_oauth() -> anyhow::Result<SpotifyOauth> {
+    let client_id = Input::new()
+        .with_prompt("spotify client_id?")
+        .interact_text()?;
+    let client_secret = Password::new()
+        .with_prompt("spotify client_secret?")
+        .interact()?;
+
+    Ok(SpotifyOauth {
+        client_id,
+        client_secret,
+    })
+}
@@ -0,0 +1,598 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::Context;
+use console::{Alignment, measure_text_width, pad_str, truncate_str};
+use indicatif::HumanDuration;
+use reqwest::Url;
+use tokio::task::JoinHandle;
+use tracing::{Instrument, info, info_span, warn};
+
+use sptfydl::{
+    download::{
+        CustomCommand, DownloadOptions, Downloaded, Downloader, YtDlp, download_many, download_one,
+        expected_file_name, ffmpeg_version, ytdlp_version,
+    },
+    playlist::{PlaylistEntry, write_m3u8, write_xspf},
+    spotify::{
+        Extraction, SearchOptions, SearchStream, Track, extract_spotify, search::REQUESTS,
+        stream_spotify, ytm_cookie,
+    },
+    tag::{TagConfig, video_id},
+};
+
+use super::{
+    credentials::spotify_tokens,
+    tagging::{GainOptions, Prepared, TagOptions, finish_tag, prepare_tag, tag_album, tag_options},
+};
+use crate::{CommonArgs, DownloadArgs};
+
+/// Search for and download every track of `args.url`.
+pub async fn download(
+    args: &DownloadArgs,
+    common: &CommonArgs,
+    tagging: TagConfig,
+) -> anyhow::Result<()> {
+    let url = args.url.as_deref().expect("the url is required");
+    if args.dry_run {
+        return dry_run(args, common).await;
+    }
+    check_download_tools(args, common).await?;
+
+    let raw_cookie = ytm_cookie(common.ytm_cookie_file.as_deref())?;
+
+    let start = Instant::now();
+    let tokens = spotify_tokens(common).await?;
+    let stream = stream_spotify(
+        tokens,
+        raw_cookie.as_deref(),
+        url,
+        search_options(args, common),
+    )
+    .await
+    .context("extracting youtube urls from spotify")?;
+
+    let output_dir = output_dir(args, stream.name.as_deref());
+    let tagging = download_tag_options(args, tagging, common.ffmpeg_location.as_deref());
+    let downloaded = download_stream(&stream, args, common, output_dir.as_deref(), tagging).await;
+
+    if stream.total > 1
+        && let Some(name) = stream.name.as_deref()
+    {
+        let dir = output_dir.unwrap_or_default();
+        write_playlists(&dir, name, &downloaded, !args.no_m3u8, args.xspf);
+    }
+
+    report(stream, start).await;
+    Ok(())
+}
+
+/// Search for every track of `args.url` like [`download`], then print what would be downloaded and where.
+async fn dry_run(args: &DownloadArgs, common: &CommonArgs) -> anyhow::Result<()> {
+    let url = args.url.as_deref().expect("the url is required");
+    let raw_cookie = ytm_cookie(common.ytm_cookie_file.as_deref())?;
+
+    let start = Instant::now();
+    let tokens = spotify_tokens(common).await?;
+    let extraction = extract_spotify(
+        tokens,
+        raw_cookie.as_deref(),
+        url,
+        search_options(args, common),
+    )
+    .await
+    .context("extracting youtube urls from spotify")?;
+
+    let output_dir = output_dir(args, extraction.name.as_deref());
+    print_extraction(&extraction, args, output_dir.as_deref());
+
+    info!(
+        "took {} to search {} tracks, without downloading them",
+        HumanDuration(start.elapsed()),
+        extraction.tracks.len() + extraction.failures
+    );
+    info!("used {REQUESTS:?} spotify api calls in total");
+    Ok(())
+}
+
+/// Print a table of the tracks in `extraction`: what was found on youtube, and an estimate of where it would be
+/// downloaded to.
+pub fn print_extraction(extraction: &Extraction, args: &DownloadArgs, output_dir: Option<&Path>) {
+    const HEADER: [&str; 6] = [
+        "#",
+        "spotify",
+        "youtube",
+        "url",
+        "warning",
+        "estimated path",
+    ];
+    // long titles are cut to this
+    const MAX_TITLE: usize = 40;
+
+    let total = extraction.tracks.len() + extraction.failures;
+    let mut rows = vec![HEADER.map(ToString::to_string)];
+    for (track_num, track) in &extraction.tracks {
+        let metadata = &track.metadata;
+        let spotify = if metadata.artists.is_empty() {
+            metadata.name.clone()
+        } else {
+            format!("{} - {}", metadata.artist_names().join(", "), metadata.name)
+        };
+        let youtube = metadata
+            .search_match
+            .as_ref()
+            .map(|search_match| search_match.title.clone())
+            .unwrap_or_default();
+        let warning = match &metadata.search_match {
+            Some(search_match) if extraction.warnings.contains(track_num) => {
+                format!("best result unavailable, chose #{}", search_match.rank)
+            }
+            _ => String::new(),
+        };
+
+        let path = if args.download_command.is_some() {
+            "(chosen by the download command)".to_string()
+        } else if has_output_template(&args.ytdlp_args) {
+            "(chosen by your -o)".to_string()
+        } else {
+            let options = DownloadOptions {
+                track_num: (total > 1).then_some(*track_num),
+                track_padding: total.to_string().len(),
+                attempt: 0,
+            };
+            let id = video_id(&track.url).unwrap_or_default();
+            let ext = args.format.extension().unwrap_or("*");
+            let name = expected_file_name(&youtube, &id, ext, options);
+            output_dir
+                .map_or_else(|| PathBuf::from(&name), |dir| dir.join(&name))
+                .display()
+                .to_string()
+        };
+
+        // a single track is sent as track 0
+        let num = if total > 1 { *track_num } else { 1 };
+        rows.push([
+            num.to_string(),
+            truncate_str(&spotify, MAX_TITLE, "…").into_owned(),
+            truncate_str(&youtube, MAX_TITLE, "…").into_owned(),
+            track.url.clone(),
+            warning,
+            path,
+        ]);
+    }
+
+    let mut widths = [0; HEADER.len()];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(measure_text_width(cell));
+        }
+    }
+    for row in &rows {
+        let (last, cells) = row.split_last().expect("rows are not empty");
+        let mut line = String::new();
+        for (cell, width) in cells.iter().zip(widths) {
+            line.push_str(&pad_str(cell, width, Alignment::Left, None));
+            line.push_str("  ");
+        }
+        line.push_str(last);
+        println!("{line}");
+    }
+
+    if args.download_command.is_none() && !has_output_template(&args.ytdlp_args) {
+        println!(
+            "paths are estimated from the ytmusic titles, yt-dlp may name the files differently"
+        );
+    }
+    if extraction.failures > 0 {
+        println!(
+            "{} of {total} tracks were not found on ytmusic",
+            extraction.failures
+        );
+    }
+}
+
+/// Whether the args for yt-dlp set their own output template, like `-o '%(title)s.%(ext)s'`.
+///
+/// Templates for other files, like `-o 'thumbnail:%(title)s.%(ext)s'`, don't count.
+fn has_output_template(ytdlp_args: &[String]) -> bool {
+    /// The files other than the download that yt-dlp can name with `-o TYPE:TEMPLATE`.
+    const OTHER_TYPES: [&str; 11] = [
+        "subtitle",
+        "thumbnail",
+        "description",
+        "annotation",
+        "infojson",
+        "link",
+        "pl_thumbnail",
+        "pl_description",
+        "pl_infojson",
+        "chapter",
+        "pl_video",
+    ];
+
+    let mut args = ytdlp_args.iter();
+    while let Some(arg) = args.next() {
+        let template = if arg == "-o" || arg == "--output" {
+            args.next().map(String::as_str)
+        } else if let Some(template) = arg.strip_prefix("--output=") {
+            Some(template)
+        } else if let Some(template) = arg.strip_prefix("-o").filter(|t| !t.is_empty()) {
+            Some(template)
+        } else {
+            continue;
+        };
+        let Some(template) = template else {
+            return false;
+        };
+        let other = template
+            .split_once(':')
+            .is_some_and(|(kind, _)| OTHER_TYPES.contains(&kind));
+        if !other {
+            return true;
+        }
+    }
+    false
+}
+
+/// Where to download to: `args.path`, or else a directory named after the playlist or album.
+pub fn output_dir(args: &DownloadArgs, name: Option<&str>) -> Option<PathBuf> {
+    args.path
+        .clone()
+        .or_else(|| name.map(|n| n.replace('/', "_").into()))
+}
+
+pub fn search_options(args: &DownloadArgs, common: &CommonArgs) -> SearchOptions {
+    SearchOptions {
+        searchers: args.searchers,
+        no_interaction: common.no_interaction,
+        retries: args.searching.search_retries,
+        isrc: args.searching.isrc,
+    }
+}
+
+/// Make sure the tools `args` downloads and tags with can be run.
+pub async fn check_download_tools(args: &DownloadArgs, common: &CommonArgs) -> anyhow::Result<()> {
+    let uses_ytdlp = args.download_command.is_none();
+    let needs_ffmpeg = (uses_ytdlp && args.format.ytdlp_audio_format().is_some())
+        || args.tagging.cover_max_size.is_some()
+        || args.tagging.replaygain;
+    check_tools(
+        uses_ytdlp.then(|| ytdlp_path(args)).as_deref(),
+        common.ffmpeg_location.as_deref(),
+        needs_ffmpeg,
+    )
+    .await
+}
+
+fn ytdlp_path(args: &DownloadArgs) -> PathBuf {
+    args.ytdlp_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("yt-dlp"))
+}
+
+/// Download and tag every track of `stream` to `output_dir`, with yt-dlp or the download command.
+///
+/// Returns the successfully downloaded tracks, in track order.
+pub async fn download_stream(
+    stream: &SearchStream,
+    args: &DownloadArgs,
+    common: &CommonArgs,
+    output_dir: Option<&Path>,
+    tagging: TagOptions,
+) -> Vec<PlaylistEntry> {
+    if let Some(program) = args.download_command.clone() {
+        let downloader = CustomCommand::new(
+            program,
+            args.ytdlp_args.clone(),
+            output_dir.map(Path::to_path_buf),
+        );
+        download_all(downloader, stream, args, tagging).await
+    } else {
+        let mut ytdlp_args = ytdlp_args(args, common);
+        if let Some(path) = output_dir {
+            ytdlp_args.extend(["-P".to_string(), path.to_string_lossy().into_owned()]);
+        }
+        let downloader = YtDlp::new(ytdlp_path(args), ytdlp_args, args.show_ytdlp);
+        download_all(downloader, stream, args, tagging).await
+    }
+}
+
+/// Wait for the searches of `stream` to finish, and report how they went.
+pub async fn report(stream: SearchStream, start: Instant) {
+    let total = stream.total;
+    let report = stream.finish().await;
+
+    info!(
+        "took {} to search and download {total} tracks",
+        HumanDuration(start.elapsed()),
+    );
+
+    info!("used {REQUESTS:?} spotify api calls in total");
+
+    if !report.warnings.is_empty() {
+        warn!("these tracks could be incorrect: {:#?}", report.warnings);
+    }
+
+    if !report.failed.is_empty() {
+        warn!(
+            "{} songs failed to search, check report named `failed-...txt`",
+            report.failed.len()
+        );
+    }
+}
+
+/// The args to pass to every yt-dlp invocation, except for the output path.
+fn ytdlp_args(args: &DownloadArgs, common: &CommonArgs) -> Vec<String> {
+    let mut ytdlp_args = args.ytdlp_args.clone();
+
+    if let Some(location) = common.ffmpeg_location.as_deref() {
+        ytdlp_args.extend([
+            "--ffmpeg-location".to_string(),
+            location.to_string_lossy().into_owned(),
+        ]);
+    }
+
+    if let Some(proxy) = common.proxy.clone() {
+        ytdlp_args.extend(["--proxy".to_string(), proxy]);
+    }
+    ytdlp_args.extend([
+        "--socket-timeout".to_string(),
+        common.read_timeout.to_string(),
+    ]);
+    if common.force_ipv4 {
+        ytdlp_args.push("--force-ipv4".to_string());
+    } else if common.force_ipv6 {
+        ytdlp_args.push("--force-ipv6".to_string());
+    }
+
+    ytdlp_args.push("--no-playlist".to_string());
+    ytdlp_args.push("--restrict-filenames".to_string());
+
+    if let Some(audio_format) = args.format.ytdlp_audio_format() {
+        ytdlp_args.push("--extract-audio".to_string());
+        ytdlp_args.extend(["--audio-format", audio_format].map(ToString::to_string));
+        if let Some(quality) = args.audio_quality.clone() {
+            ytdlp_args.extend(["--audio-quality".to_string(), quality]);
+        }
+    } else if args.audio_quality.is_some() {
+        warn!("--audio-quality is ignored when not converting");
+    }
+
+    ytdlp_args
+}
+
+/// How to tag the files downloaded by `args`, according to them and the `tagging` config.
+pub fn download_tag_options(
+    args: &DownloadArgs,
+    tagging: TagConfig,
+    ffmpeg_location: Option<&Path>,
+) -> TagOptions {
+    let options = tag_options(&args.tagging, tagging, ffmpeg_location);
+    let album = args.url.as_deref().is_some_and(is_album_url);
+    TagOptions {
+        enabled: !args.no_metadata,
+        warn: args.format.taggable(),
+        cover_file: args.cover_file.clone().filter(|_| album),
+        // looking up and measuring is only useful when tagging, except for lyrics saved to .lrc files
+        lyrics: options.lyrics.filter(|_| !args.no_metadata || options.lrc),
+        musicbrainz: options.musicbrainz.filter(|_| !args.no_metadata),
+        replaygain: options
+            .replaygain
+            .filter(|_| !args.no_metadata)
+            .map(|replaygain| GainOptions {
+                album,
+                ..replaygain
+            }),
+        ..options
+    }
+}
+
+/// Make sure yt-dlp (if it's used), and ffmpeg if it's needed, can be run, before anything is searched.
+pub async fn check_tools(
+    ytdlp: Option<&Path>,
+    ffmpeg_location: Option<&Path>,
+    needs_ffmpeg: bool,
+) -> anyhow::Result<()> {
+    if let Some(ytdlp) = ytdlp {
+        let version = ytdlp_version(ytdlp).await.context(
+            "yt-dlp is required: install it (https://github.com/yt-dlp/yt-dlp#installation), \
+            or point --ytdlp-path (or `ytdlp_path` in config.yaml) to it",
+        )?;
+        info!("using yt-dlp {version}");
+    }
+
+    if needs_ffmpeg {
+        let version = ffmpeg_version(ffmpeg_location).await.context(
+            "ffmpeg is required to convert audio and resize covers: install it (https://ffmpeg.org/download.html), \
+            point --ffmpeg-location (or `ffmpeg_location` in config.yaml) to it, \
+            or use `--format original` without `--cover-max-size`",
+        )?;
+        info!("using ffmpeg {version}");
+    }
+
+    Ok(())
+}
+
+fn is_album_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| url.path().starts_with("/album"))
+}
+
+/// Write the playlist files for the `downloaded` tracks of playlist `name` to `dir`.
+pub fn write_playlists(
+    dir: &Path,
+    name: &str,
+    downloaded: &[PlaylistEntry],
+    m3u8: bool,
+    xspf: bool,
+) {
+    let file_name = name.replace('/', "_");
+
+    if m3u8 {
+        let path = dir.join(format!("{file_name}.m3u8"));
+        match write_m3u8(&path, downloaded) {
+            Ok(()) => info!("wrote playlist to {path:?}"),
+            Err(err) => warn!("failed to write {path:?}: {err}"),
+        }
+    }
+
+    if xspf {
+        let path = dir.join(format!("{file_name}.xspf"));
+        match write_xspf(&path, name, downloaded) {
+            Ok(()) => info!("wrote playlist to {path:?}"),
+            Err(err) => warn!("failed to write {path:?}: {err}"),
+        }
+    }
+}
+
+/// Download and tag every track of `stream` with `downloader`.
+///
+/// Returns the successfully downloaded tracks, in track order.
+async fn download_all<D: Downloader + 'static>(
+    downloader: D,
+    stream: &SearchStream,
+    args: &DownloadArgs,
+    tagging: TagOptions,
+) -> Vec<PlaylistEntry> {
+    if stream.total == 1 {
+        let Ok((_, track)) = stream.tracks.recv().await else {
+            return Vec::new();
+        };
+
+        info!("downloading {}", track.url);
+        let Some(path) = download_one(&track, args.download_retries, &downloader).await else {
+            warn!("failed to download {}", track.url);
+            return Vec::new();
+        };
+
+        let entry = PlaylistEntry::new(&path, &track.metadata);
+        let prepared = prepare_tag(path, track.metadata, Some(track.url), &tagging).await;
+        if tagging.replaygain.as_ref().is_some_and(|r| r.album) {
+            tag_album(vec![prepared], true, &tagging).await;
+        } else {
+            finish_tag(prepared, None, &tagging).await;
+        }
+        return vec![entry];
+    }
+
+    let (downloaded_tx, downloaded_rx) = async_channel::bounded(stream.total);
+    let taggers = spawn_taggers(args.tagging.taggers, &downloaded_rx, &tagging);
+
+    let given_up = download_many(
+        stream.tracks.clone(),
+        stream.total,
+        args.downloaders,
+        args.download_retries,
+        Arc::new(downloader),
+        downloaded_tx,
+    )
+    .await;
+
+    if !given_up.is_empty() {
+        warn!(
+            "{} tracks failed to download: {:#?}",
+            given_up.len(),
+            given_up
+        );
+    }
+
+    let mut tagged = Vec::with_capacity(stream.total);
+    let mut deferred = Vec::new();
+    for handle in taggers {
+        match handle.await {
+            Ok(output) => {
+                tagged.extend(output.tagged);
+                deferred.extend(output.deferred);
+            }
+            Err(err) => warn!("a tagger failed: {err}"),
+        }
+    }
+
+    if !deferred.is_empty() {
+        let whole = deferred.len() == stream.total;
+        tag_album(deferred, whole, &tagging).await;
+    }
+
+    // keep the original playlist order
+    tagged.sort_unstable_by_key(|(track_num, _)| *track_num);
+    tagged.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// What a tagger task did.
+#[derive(Default)]
+struct TaggerOutput {
+    tagged: Vec<(usize, PlaylistEntry)>,
+    /// tracks left to tag once every track of the album is measured
+    deferred: Vec<Prepared>,
+}
+
+/// Spawn `taggers` tasks that tag every downloaded file from `to_tag`, ending once it closes.
+///
+/// Each task returns the tracks it tagged, and those it deferred.
+fn spawn_taggers(
+    taggers: usize,
+    to_tag: &async_channel::Receiver<Downloaded>,
+    options: &TagOptions,
+) -> Vec<JoinHandle<TaggerOutput>> {
+    let mut handles = Vec::with_capacity(taggers);
+    for task in 0..taggers {
+        let to_tag = to_tag.clone();
+        let options = options.clone();
+
+        let handle = tokio::spawn(
+            async move {
+                let mut output = TaggerOutput::default();
+                while let Ok(downloaded) = to_tag.recv().await {
+                    let Downloaded {
+                        track_num,
+                        path,
+                        track: Track { url, metadata },
+                    } = downloaded;
+
+                    output
+                        .tagged
+                        .push((track_num, PlaylistEntry::new(&path, &metadata)));
+                    let prepared = prepare_tag(path, metadata, Some(url), &options).await;
+                    if options.replaygain.as_ref().is_some_and(|r| r.album) {
+                        output.deferred.push(prepared);
+                    } else {
+                        finish_tag(prepared, None, &options).await;
+                    }
+                }
+                output
+            }
+            .instrument(info_span!("tagger", id = task + 1)),
+        );
+        handles.push(handle);
+    }
+    handles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::has_output_template;
+
+    #[test]
+    fn output_templates() {
+        let has = |args: &[&str]| {
+            has_output_template(&args.iter().map(ToString::to_string).collect::<Vec<_>>())
+        };
+        assert!(has(&["-o", "%(title)s.%(ext)s"]));
+        assert!(has(&["--output", "%(title)s.%(ext)s"]));
+        assert!(has(&["-o%(title)s.%(ext)s"]));
+        assert!(has(&["--output=%(title)s.%(ext)s"]));
+        assert!(has(&[
+            "-o",
+            "thumbnail:%(title)s.%(ext)s",
+            "-o",
+            "x.%(ext)s"
+        ]));
+
+        assert!(!has(&["--output-na-placeholder", "x"]));
+        assert!(!has(&["--other", "-o"]));
+        assert!(!has(&["-o", "thumbnail:%(title)s.%(ext)s"]));
+        assert!(!has(&["--embed-thumbnail"]));
+    }
+}
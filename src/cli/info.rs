@@ -0,0 +1,56 @@
+use anyhow::Context;
+
+use sptfydl::spotify::{get_from_url, search::SpotifyTrack};
+
+use super::credentials::spotify_tokens;
+use crate::CommonArgs;
+
+pub fn album_name(track: &SpotifyTrack) -> Option<&str> {
+    track.album.as_ref()?.get("name")?.as_str()
+}
+
+/// `artists - title [album] (m:ss)`
+pub fn describe(track: &SpotifyTrack) -> String {
+    let artists = track
+        .artists
+        .iter()
+        .filter_map(|a| a.name.as_deref())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let album = album_name(track)
+        .map(|album| format!(" [{album}]"))
+        .unwrap_or_default();
+    let secs = track.duration_ms / 1000;
+    format!(
+        "{artists} - {}{album} ({}:{:02})",
+        track.name,
+        secs / 60,
+        secs % 60
+    )
+}
+
+/// Print the tracks of `url`.
+pub async fn print_info(url: &str, common: &CommonArgs) -> anyhow::Result<()> {
+    let tokens = spotify_tokens(common).await?;
+    let (tracks, name) = get_from_url(url, &tokens)
+        .await
+        .context("getting the tracks from spotify")?;
+
+    if let Some(name) = name {
+        println!("{name}");
+    }
+    println!("{} tracks", tracks.len());
+    for (i, track) in tracks.iter().enumerate() {
+        println!("{:>3}. {}", i + 1, describe(track));
+        let isrc = track
+            .external_ids
+            .as_ref()
+            .and_then(|ids| ids.isrc.as_deref())
+            .unwrap_or("unknown");
+        println!(
+            "     isrc {isrc}, https://open.spotify.com/track/{}",
+            track.id
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,10 @@
+pub mod auth;
+pub mod cache;
+pub mod config;
+pub mod credentials;
+pub mod download;
+pub mod info;
+pub mod retag;
+pub mod search;
+pub mod sync;
+pub mod tagging;
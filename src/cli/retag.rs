@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{Context, anyhow};
+use indicatif::HumanDuration;
+use tracing::{Instrument, info, info_span, warn};
+
+use sptfydl::{
+    spotify::{search::REQUESTS, tracks_metadata},
+    tag::{Embedded, TagConfig, is_audio_file, parse_track_id, read_embedded},
+};
+
+use super::{
+    credentials::spotify_tokens,
+    download::check_tools,
+    tagging::{finish_tag, prepare_tag, tag_options},
+};
+use crate::{CommonArgs, RetagArgs};
+
+/// Rewrite the tags of the audio files in `retag_args.dir` with fresh metadata from spotify.
+///
+/// Files are matched to spotify tracks by the mapping file, or by their embedded track ids.
+pub async fn retag(
+    retag_args: &RetagArgs,
+    common: &CommonArgs,
+    tagging: TagConfig,
+) -> anyhow::Result<()> {
+    let needs_ffmpeg = retag_args.tagging.cover_max_size.is_some() || retag_args.tagging.replaygain;
+    check_tools(None, common.ffmpeg_location.as_deref(), needs_ffmpeg).await?;
+
+    let start = Instant::now();
+    let dir = &retag_args.dir;
+
+    let mapping: HashMap<PathBuf, String> = match retag_args.map.as_deref() {
+        Some(path) => {
+            let mapping = fs::read_to_string(path).context("reading the retag mapping")?;
+            serde_yaml::from_str(&mapping).context("parsing the retag mapping")?
+        }
+        None => HashMap::new(),
+    };
+
+    let files = audio_files(dir).context("reading the directory to retag")?;
+    info!("found {} audio files in {}", files.len(), dir.display());
+
+    let mut found = Vec::with_capacity(files.len());
+    for path in files {
+        let embedded = read_embedded(&path).unwrap_or_else(|err| {
+            warn!("failed to read the tags of {path:?}: {err}");
+            Embedded::default()
+        });
+        let mapped = path
+            .strip_prefix(dir)
+            .ok()
+            .and_then(|relative| mapping.get(relative))
+            .or_else(|| mapping.get(&path));
+
+        let track_id = track_id(&path, mapped.map(String::as_str), &embedded);
+        if let Some(track_id) = track_id {
+            found.push((path, track_id, embedded.url()));
+        } else if mapped.is_none() {
+            warn!("no spotify track id for {path:?}, add it with --map");
+        }
+    }
+
+    if found.is_empty() {
+        return Err(anyhow!("found no files to retag"));
+    }
+
+    let tokens = spotify_tokens(common).await?;
+    let ids: Vec<&str> = found.iter().map(|(_, id, _)| id.as_str()).collect();
+    let metadata = tracks_metadata(&ids, &tokens)
+        .await
+        .context("getting metadata from spotify")?;
+
+    let (to_tag, to_tag_rx) = async_channel::bounded(found.len());
+    for ((path, track_id, url), metadata) in found.into_iter().zip(metadata) {
+        if let Some(metadata) = metadata {
+            to_tag
+                .try_send((path, metadata, url))
+                .expect("channel fits every file");
+        } else {
+            warn!("spotify track {track_id} of {path:?} was not found");
+        }
+    }
+    let total = to_tag.len();
+    to_tag.close();
+
+    let options = tag_options(
+        &retag_args.tagging,
+        tagging,
+        common.ffmpeg_location.as_deref(),
+    );
+    let mut handles = Vec::with_capacity(retag_args.tagging.taggers);
+    for task in 0..retag_args.tagging.taggers {
+        let to_tag = to_tag_rx.clone();
+        let options = options.clone();
+        let handle = tokio::spawn(
+            async move {
+                while let Ok((path, metadata, url)) = to_tag.recv().await {
+                    let prepared = prepare_tag(path, metadata, url, &options).await;
+                    finish_tag(prepared, None, &options).await;
+                }
+            }
+            .instrument(info_span!("tagger", id = task + 1)),
+        );
+        handles.push(handle);
+    }
+    for handle in handles {
+        if let Err(err) = handle.await {
+            warn!("a tagger failed: {err}");
+        }
+    }
+
+    info!(
+        "took {} to retag {total} files",
+        HumanDuration(start.elapsed())
+    );
+    info!("used {REQUESTS:?} spotify api calls in total");
+
+    Ok(())
+}
+
+/// The spotify track id of the file at `path`: the one it's `mapped` to, or else the `embedded` one.
+fn track_id(path: &Path, mapped: Option<&str>, embedded: &Embedded) -> Option<String> {
+    let Some(mapped) = mapped else {
+        return embedded.track_id.clone();
+    };
+    if let Some(track_id) = parse_track_id(mapped) {
+        return Some(track_id.to_string());
+    }
+
+    let fallback = if embedded.track_id.is_some() {
+        "using its embedded track id"
+    } else {
+        "skipping it"
+    };
+    warn!("the --map entry of {path:?} is not a spotify track url or id: {mapped:?}, {fallback}");
+    embedded.track_id.clone()
+}
+
+/// The audio files in `dir` and its subdirectories, sorted.
+pub fn audio_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_audio_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort_unstable();
+    Ok(files)
+}
@@ -0,0 +1,49 @@
+use anyhow::Context;
+
+use sptfydl::{
+    spotify::{SearchOptions, get_from_url, search_candidates, ytm_cookie},
+    ytmusic::auth::Browser,
+};
+
+use super::{credentials::spotify_tokens, info::describe};
+use crate::{CommonArgs, SearchArgs};
+
+/// Search ytmusic for the tracks of `search_args.url`, printing the best results of each.
+pub async fn print_candidates(search_args: &SearchArgs, common: &CommonArgs) -> anyhow::Result<()> {
+    let auth = ytm_cookie(common.ytm_cookie_file.as_deref())?
+        .as_deref()
+        .map(Browser::from_cookie)
+        .transpose()
+        .context("reading the ytm cookie")?;
+
+    let tokens = spotify_tokens(common).await?;
+    let (tracks, _) = get_from_url(&search_args.url, &tokens)
+        .await
+        .context("getting the tracks from spotify")?;
+
+    let options = SearchOptions {
+        isrc: search_args.searching.isrc,
+        retries: search_args.searching.search_retries,
+        ..SearchOptions::default()
+    };
+    for (i, track) in tracks.iter().enumerate() {
+        println!("{:>3}. {}", i + 1, describe(track));
+        let Some((strategy, results)) = search_candidates(track, auth.as_ref(), options).await
+        else {
+            println!("     the search failed");
+            continue;
+        };
+        if results.is_empty() {
+            println!("     no results, searched by {}", strategy.as_str());
+            continue;
+        }
+        println!("     searched by {}:", strategy.as_str());
+        for (rank, result) in results.iter().take(search_args.results).enumerate() {
+            match result.link() {
+                Some(link) => println!("     {}: {} {link}", rank + 1, result.title),
+                None => println!("     {}: {} (unavailable)", rank + 1, result.title),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{Context, anyhow};
+use tracing::{debug, info, warn};
+
+use sptfydl::{
+    playlist::PlaylistEntry,
+    spotify::{get_from_url, search::SpotifyTrack, stream_tracks, ytm_cookie},
+    tag::{Embedded, TagConfig, read_embedded},
+};
+
+use super::{
+    credentials::spotify_tokens,
+    download::{
+        check_download_tools, download_stream, download_tag_options, output_dir, print_extraction,
+        report, search_options, write_playlists,
+    },
+    info::album_name,
+    retag::audio_files,
+};
+use crate::{CommonArgs, SyncArgs};
+
+/// Download the tracks of `args.url` that are not in its directory yet, then rewrite its playlists.
+///
+/// Downloaded tracks are found by the spotify track id in their tags.
+pub async fn sync(
+    sync_args: &SyncArgs,
+    common: &CommonArgs,
+    tagging: TagConfig,
+) -> anyhow::Result<()> {
+    let args = &sync_args.download;
+    let url = args.url.as_deref().expect("the url is required");
+    if args.no_metadata {
+        return Err(anyhow!(
+            "sync finds downloaded tracks by their tags, so it can't be used with --no-metadata"
+        ));
+    }
+    if !args.dry_run {
+        check_download_tools(args, common).await?;
+    }
+
+    let raw_cookie = ytm_cookie(common.ytm_cookie_file.as_deref())?;
+
+    let start = Instant::now();
+    let tokens = spotify_tokens(common).await?;
+    let (spotify_tracks, name) = get_from_url(url, &tokens)
+        .await
+        .context("getting the tracks from spotify")?;
+    let total = spotify_tracks.len();
+
+    let output_dir = output_dir(args, name.as_deref());
+    if sync_args.delete && output_dir.is_none() {
+        return Err(anyhow!(
+            "--delete needs the directory to sync: pass --path, or sync a playlist or album"
+        ));
+    }
+    let dir = output_dir.clone().unwrap_or_else(|| PathBuf::from("."));
+    let downloaded = downloaded_tracks(&dir)?;
+
+    if sync_args.delete {
+        delete_removed(&downloaded, &spotify_tracks, args.dry_run)?;
+    }
+
+    let order: Vec<String> = spotify_tracks.iter().map(|t| t.id.clone()).collect();
+    let mut entries = HashMap::new();
+    let mut missing = Vec::new();
+    for track in spotify_tracks {
+        if let Some(path) = downloaded.get(&track.id) {
+            entries.insert(track.id.clone(), existing_entry(path, &track));
+        } else {
+            missing.push(track);
+        }
+    }
+    info!(
+        "{} of {total} tracks are already downloaded",
+        total - missing.len()
+    );
+
+    if args.dry_run {
+        if !missing.is_empty() {
+            let extraction = stream_tracks(
+                tokens,
+                raw_cookie.as_deref(),
+                missing,
+                name,
+                search_options(args, common),
+            )?
+            .extract()
+            .await
+            .context("extracting youtube urls from spotify")?;
+            print_extraction(&extraction, args, output_dir.as_deref());
+        }
+        return Ok(());
+    }
+
+    if !missing.is_empty() {
+        let stream = stream_tracks(
+            tokens,
+            raw_cookie.as_deref(),
+            missing,
+            name.clone(),
+            search_options(args, common),
+        )?;
+
+        let mut tagging = download_tag_options(args, tagging, common.ffmpeg_location.as_deref());
+        // the album's gain needs every track measured
+        if !entries.is_empty()
+            && let Some(replaygain) = tagging.replaygain.as_mut()
+        {
+            replaygain.album = false;
+        }
+        let new = download_stream(&stream, args, common, output_dir.as_deref(), tagging).await;
+        entries.extend(
+            new.into_iter()
+                .map(|entry| (entry.spotify_id.clone(), entry)),
+        );
+
+        report(stream, start).await;
+    }
+
+    if total > 1
+        && let Some(name) = name.as_deref()
+    {
+        let entries: Vec<_> = order
+            .iter()
+            .filter_map(|id| entries.get(id).cloned())
+            .collect();
+        write_playlists(&dir, name, &entries, !args.no_m3u8, args.xspf);
+    }
+
+    Ok(())
+}
+
+/// Delete the `downloaded` files of tracks that are not in `spotify_tracks`, or only say so if `dry_run`.
+fn delete_removed(
+    downloaded: &HashMap<String, PathBuf>,
+    spotify_tracks: &[SpotifyTrack],
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let wanted: HashSet<&str> = spotify_tracks.iter().map(|t| t.id.as_str()).collect();
+    for (id, path) in downloaded {
+        if wanted.contains(id.as_str()) {
+            continue;
+        }
+        if dry_run {
+            println!("would delete {}", path.display());
+        } else {
+            fs::remove_file(path).with_context(|| format!("deleting {}", path.display()))?;
+            info!("deleted {}, which is no longer wanted", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// The audio files in `dir` and its subdirectories, by the spotify track id in their tags.
+fn downloaded_tracks(dir: &Path) -> anyhow::Result<HashMap<String, PathBuf>> {
+    let mut tracks = HashMap::new();
+    if !dir.exists() {
+        return Ok(tracks);
+    }
+
+    for path in audio_files(dir).context("reading the directory to sync")? {
+        match read_embedded(&path) {
+            Ok(Embedded {
+                track_id: Some(id), ..
+            }) => {
+                tracks.insert(id, path);
+            }
+            Ok(_) => debug!("{path:?} has no spotify track id"),
+            Err(err) => warn!("failed to read the tags of {path:?}: {err}"),
+        }
+    }
+    Ok(tracks)
+}
+
+/// The playlist entry of `track`, already downloaded to `path`.
+fn existing_entry(path: &Path, track: &SpotifyTrack) -> PlaylistEntry {
+    PlaylistEntry {
+        path: path.to_path_buf(),
+        spotify_id: track.id.clone(),
+        title: track.name.clone(),
+        artists: track
+            .artists
+            .iter()
+            .filter_map(|a| a.name.clone())
+            .collect(),
+        album: album_name(track).map(ToString::to_string),
+        duration_ms: track.duration_ms,
+    }
+}
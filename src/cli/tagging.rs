@@ -0,0 +1,243 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use tracing::{debug, info, warn};
+
+use sptfydl::{
+    download::ffmpeg_program,
+    lyrics::{Lrclib, LyricsProvider, write_lrc},
+    musicbrainz::{MusicBrainz, MusicBrainzIds},
+    spotify::Metadata,
+    tag::{
+        CoverCache, CoverResize, Extras, Gain, Loudness, ReplayGain, TagConfig, TagField, Tagger,
+        measure_loudness,
+    },
+};
+
+use crate::TagArgs;
+
+/// How to tag files, according to `args` and the `tagging` config. Tracks are tagged one at a time,
+/// and failures are warned about.
+pub fn tag_options(
+    args: &TagArgs,
+    mut tagging: TagConfig,
+    ffmpeg_location: Option<&Path>,
+) -> TagOptions {
+    if let Some(separator) = args.tag_separator.clone() {
+        tagging.separator = Some(separator);
+    }
+    if args.no_comment {
+        tagging.skip.push(TagField::Comment);
+    }
+
+    let cover_resize = args.cover_max_size.map(|max_size| CoverResize {
+        ffmpeg: ffmpeg_program(ffmpeg_location),
+        max_size,
+    });
+    TagOptions {
+        enabled: true,
+        warn: true,
+        tagger: Arc::new(Tagger {
+            config: tagging,
+            covers: Arc::new(CoverCache::new(args.cache_covers, cover_resize)),
+        }),
+        lyrics: (args.lyrics || args.lrc).then(|| Lrclib::new(&args.lyrics_url)),
+        lrc: args.lrc,
+        musicbrainz: args
+            .musicbrainz
+            .then(|| MusicBrainz::new(&args.musicbrainz_url)),
+        cover_file: None,
+        replaygain: args.replaygain.then(|| GainOptions {
+            ffmpeg: ffmpeg_program(ffmpeg_location),
+            album: false,
+        }),
+    }
+}
+
+#[derive(Clone)]
+pub struct TagOptions {
+    pub enabled: bool,
+    /// whether to warn on failure
+    pub warn: bool,
+    pub tagger: Arc<Tagger>,
+    pub lyrics: Option<Lrclib>,
+    /// write .lrc files
+    pub lrc: bool,
+    pub musicbrainz: Option<MusicBrainz>,
+    /// the name to save album covers as
+    pub cover_file: Option<String>,
+    pub replaygain: Option<GainOptions>,
+}
+
+#[derive(Clone)]
+pub struct GainOptions {
+    /// the ffmpeg to measure loudness with
+    pub ffmpeg: PathBuf,
+    /// hold off tagging until the whole album is measured, to write its gain
+    pub album: bool,
+}
+
+/// A downloaded track, ready to be tagged.
+pub struct Prepared {
+    path: PathBuf,
+    metadata: Metadata,
+    /// where the file was downloaded from, if known
+    url: Option<String>,
+    lyrics: Option<String>,
+    musicbrainz: Option<MusicBrainzIds>,
+    loudness: Option<Loudness>,
+}
+
+/// Find lyrics and musicbrainz ids, save the cover file and measure loudness for the file at `path`.
+pub async fn prepare_tag(
+    path: PathBuf,
+    metadata: Metadata,
+    url: Option<String>,
+    options: &TagOptions,
+) -> Prepared {
+    let lyrics = match options.lyrics.as_ref() {
+        Some(provider) => find_lyrics(provider, &path, &metadata, options.lrc).await,
+        None => None,
+    };
+    let musicbrainz = match options.musicbrainz.as_ref() {
+        Some(musicbrainz) => find_musicbrainz_ids(musicbrainz, &metadata).await,
+        None => None,
+    };
+
+    if let Some(name) = options.cover_file.as_deref()
+        && let Some(cover_url) = metadata.cover_url.as_deref()
+    {
+        let cover_path = path.with_file_name(name);
+        match options.tagger.covers.write_to(cover_url, &cover_path).await {
+            Ok(Some(written)) => info!("saved cover to {}", written.display()),
+            Ok(None) => (),
+            Err(err) => warn!("failed to save cover to {}: {err}", cover_path.display()),
+        }
+    }
+
+    let loudness = match options.replaygain.as_ref() {
+        Some(replaygain) => match measure_loudness(&replaygain.ffmpeg, &path).await {
+            Ok(loudness) => Some(loudness),
+            Err(err) => {
+                warn!("failed to measure the loudness of {path:?}: {err}");
+                None
+            }
+        },
+        None => None,
+    };
+
+    Prepared {
+        path,
+        metadata,
+        url,
+        lyrics,
+        musicbrainz,
+        loudness,
+    }
+}
+
+/// only warns if the format is always taggable, but still tag `original` files in case they happen to be supported.
+pub async fn finish_tag(prepared: Prepared, album_gain: Option<Gain>, options: &TagOptions) {
+    let Prepared {
+        path,
+        metadata,
+        url,
+        lyrics,
+        musicbrainz,
+        loudness,
+    } = prepared;
+    let extras = Extras {
+        lyrics,
+        replaygain: loudness.map(|loudness| ReplayGain {
+            track: loudness.gain(),
+            album: album_gain,
+        }),
+        musicbrainz,
+    };
+
+    if options.enabled
+        && let Err(err) = options
+            .tagger
+            .tag(&path, metadata, url.as_deref(), extras)
+            .await
+        && options.warn
+    {
+        warn!("failed to tag file {path:?}: {err}");
+    }
+}
+
+/// Tag the `tracks` of an album, with the album's gain if it was downloaded `whole` and every track was measured.
+pub async fn tag_album(tracks: Vec<Prepared>, whole: bool, options: &TagOptions) {
+    let measured: Option<Vec<_>> = tracks
+        .iter()
+        .map(|track| {
+            let duration = Duration::from_millis(track.metadata.duration_ms.into());
+            Some((track.loudness?, duration))
+        })
+        .collect();
+
+    let album_gain = match measured {
+        Some(measured) if whole => Loudness::album(&measured).map(Loudness::gain),
+        _ => {
+            warn!("not writing album gain, since not every track was downloaded and measured");
+            None
+        }
+    };
+
+    for track in tracks {
+        finish_tag(track, album_gain, options).await;
+    }
+}
+
+/// Find the musicbrainz ids of `metadata`, warning if the lookup fails.
+async fn find_musicbrainz_ids(
+    musicbrainz: &MusicBrainz,
+    metadata: &Metadata,
+) -> Option<MusicBrainzIds> {
+    match musicbrainz.lookup(metadata).await {
+        Ok(Some(ids)) => Some(ids),
+        Ok(None) => {
+            info!("{:?} is not on musicbrainz", metadata.name);
+            None
+        }
+        Err(err) => {
+            warn!(
+                "failed to look up {:?} on musicbrainz: {err}",
+                metadata.name
+            );
+            None
+        }
+    }
+}
+
+/// Find the lyrics to embed for `metadata`, writing an .lrc file next to `path` if `lrc`.
+async fn find_lyrics(
+    provider: &impl LyricsProvider,
+    path: &Path,
+    metadata: &Metadata,
+    lrc: bool,
+) -> Option<String> {
+    let lyrics = match provider.lyrics(metadata).await {
+        Ok(Some(lyrics)) => lyrics,
+        Ok(None) => {
+            info!("no lyrics found for {:?}", metadata.name);
+            return None;
+        }
+        Err(err) => {
+            warn!("failed to find lyrics for {:?}: {err}", metadata.name);
+            return None;
+        }
+    };
+
+    if lrc && let Some(synced) = lyrics.synced.as_deref() {
+        match write_lrc(path, synced) {
+            Ok(lrc) => debug!("wrote lyrics to {}", lrc.display()),
+            Err(err) => warn!("failed to write lyrics for {}: {err}", path.display()),
+        }
+    }
+
+    lyrics.preferred().map(ToString::to_string)
+}
@@ -0,0 +1,85 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use anyhow::anyhow;
+use tokio::process::Command;
+use tracing::{Span, debug, instrument};
+
+use super::{DownloadOptions, Downloader, redir_output};
+
+/// Downloads by running a user-provided command, like a script.
+///
+/// The command is run with the url as its last argument, and these environment variables:
+/// - `SPTFYDL_OUTPUT_DIR`: the directory to download to, if one was chosen.
+/// - `SPTFYDL_TRACK`: the zero-padded track number, if downloading more than one track.
+///
+/// It should print the path of the downloaded file as the last line of its stdout, and exit with a non-zero status on failure.
+#[derive(Debug, Clone)]
+pub struct CustomCommand {
+    program: PathBuf,
+    args: Vec<String>,
+    output_dir: Option<PathBuf>,
+}
+
+impl CustomCommand {
+    /// Run `program` with `args` before the url.
+    #[must_use]
+    pub fn new(program: PathBuf, args: Vec<String>, output_dir: Option<PathBuf>) -> Self {
+        Self {
+            program,
+            args,
+            output_dir,
+        }
+    }
+}
+
+impl Downloader for CustomCommand {
+    #[instrument(name = "command", skip_all, fields(track = ?options.track_num, try = options.attempt + 1))]
+    async fn download(&self, url: &str, options: DownloadOptions) -> anyhow::Result<PathBuf> {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args).arg(url);
+
+        if let Some(dir) = self.output_dir.as_deref() {
+            command.env("SPTFYDL_OUTPUT_DIR", dir);
+        }
+        if let Some(track) = options.track_prefix() {
+            command.env("SPTFYDL_TRACK", track);
+        }
+
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| anyhow!("failed to run {}: {err}", self.program.display()))?;
+
+        redir_output(
+            Span::current(),
+            child.stderr.take().expect("stderr is always captured"),
+            true,
+        );
+
+        let result = child.wait_with_output().await?;
+
+        if !result.status.success() {
+            return Err(anyhow!(
+                "{} terminated with {}",
+                self.program.display(),
+                result.status
+            ));
+        }
+
+        let stdout = String::from_utf8(result.stdout)?;
+        let Some(path) = stdout.lines().rfind(|l| !l.trim().is_empty()) else {
+            return Err(anyhow!(
+                "{} did not print the downloaded file",
+                self.program.display()
+            ));
+        };
+        debug!("downloaded to {path}");
+
+        Ok(Path::new(path.trim()).to_path_buf())
+    }
+}
@@ -0,0 +1,312 @@
+pub mod command;
+pub mod ytdlp;
+
+pub use command::CustomCommand;
+pub use ytdlp::{YtDlp, expected_file_name, ffmpeg_program, ffmpeg_version, ytdlp_version};
+
+use std::{future::Future, path::PathBuf, sync::Arc};
+
+use indicatif::ProgressStyle;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::ChildStderr,
+    sync::mpsc,
+};
+use tracing::{Instrument, Span, debug, info, info_span, warn};
+use tracing_indicatif::span_ext::IndicatifSpanExt;
+
+use crate::spotify::Track;
+
+/// A way to download a url to a file.
+pub trait Downloader: Send + Sync {
+    /// Download `url`, returning the path of the downloaded file.
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the download failed, and should be retried.
+    fn download(
+        &self,
+        url: &str,
+        options: DownloadOptions,
+    ) -> impl Future<Output = anyhow::Result<PathBuf>> + Send;
+}
+
+/// Options for a single download.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DownloadOptions {
+    /// The track number to prefix the file name with, if any.
+    pub track_num: Option<usize>,
+    /// The width to zero-pad `track_num` to.
+    pub track_padding: usize,
+    /// The number of times this url was tried before.
+    pub attempt: usize,
+}
+
+impl DownloadOptions {
+    /// `track_num`, zero-padded to `track_padding`.
+    #[must_use]
+    pub fn track_prefix(&self) -> Option<String> {
+        self.track_num
+            .map(|track| format!("{track:0>width$}", width = self.track_padding))
+    }
+}
+
+/// A track that was downloaded to `path`.
+#[derive(Debug)]
+pub struct Downloaded {
+    pub track_num: usize,
+    pub path: PathBuf,
+    pub track: Track,
+}
+
+/// Download `total` tracks from `tracks` as soon as they come in, with `downloaders` concurrent downloads.
+///
+/// Every track is tried up to `retries` times in all, and at least once. Downloaded tracks are sent to `output`.
+///
+/// Returns the tracks that reached the retry limit.
+///
+/// # Panics
+///
+/// Will panic if `output` closes before all tracks are downloaded.
+pub async fn download_many<D: Downloader + 'static>(
+    tracks: async_channel::Receiver<(usize, Track)>,
+    total: usize,
+    downloaders: usize,
+    retries: usize,
+    downloader: Arc<D>,
+    output: async_channel::Sender<Downloaded>,
+) -> Vec<(usize, Track)> {
+    // we dont want these channels to block on `send`s
+    let (failed_tx, failed_rx) = async_channel::bounded(total);
+    let (given_up_tx, mut given_up_rx) = mpsc::channel(total);
+
+    let track_padding = total.to_string().len();
+
+    let pb_span = info_span!("pb");
+
+    pb_span.pb_set_style(
+        &ProgressStyle::with_template("{wide_bar} {pos}/{len} ({elapsed}) (eta: {eta})")
+            .expect("valid template"),
+    );
+    pb_span.pb_set_length(total as u64);
+
+    pb_span.pb_start();
+
+    let mut downloader_handles = Vec::with_capacity(downloaders);
+    for task in 0..downloaders {
+        let failed_tx = failed_tx.clone();
+        let failed_rx = failed_rx.clone();
+        let given_up = given_up_tx.clone();
+        let tracks = tracks.clone();
+        let output = output.clone();
+        let downloader = downloader.clone();
+        let pb_span = pb_span.clone();
+
+        let handle = tokio::spawn(
+            async move {
+                loop {
+                    debug!("waiting for url");
+
+                    // `tracks` will be closed once all searches end,
+                    // meaning that eventually `recv()` will return an error, letting the task end.
+                    //
+                    // conversely, the `failed` channel has multiple cloned senders,
+                    // meaning the channel will not close until all tasks end:
+                    // using `try_recv()` ensures that the task will end instead of waiting forever.
+                    //
+                    // while we wait for searches, we might as well retry failed downloads.
+                    let next = tokio::select! {
+                        biased;
+                        track = tracks.recv() => match track {
+                            Ok((i, track)) => Some((0, i, track)),
+                            Err(_) => failed_rx.try_recv().ok(),
+                        },
+                        failed = failed_rx.recv() => failed.ok(),
+                    };
+
+                    let Some((attempt, track_num, track)) = next else {
+                        debug!("no more urls");
+                        return;
+                    };
+
+                    if attempt >= retries.max(1) {
+                        warn!("track {track_num}: {} reached retry limit", track.url);
+                        given_up
+                            .send((track_num, track))
+                            .await
+                            .expect("shouldnt be closed");
+                        continue;
+                    }
+
+                    info!("track {track_num}: {}", track.url);
+                    let options = DownloadOptions {
+                        track_num: Some(track_num),
+                        track_padding,
+                        attempt,
+                    };
+
+                    match downloader.download(&track.url, options).await {
+                        Ok(path) => {
+                            pb_span.pb_inc(1);
+                            output
+                                .send(Downloaded {
+                                    track_num,
+                                    path,
+                                    track,
+                                })
+                                .await
+                                .expect("channel should be open");
+                        }
+                        Err(err) => {
+                            warn!("track {track_num}: {err}");
+                            failed_tx
+                                .send((attempt + 1, track_num, track))
+                                .await
+                                .expect("channel should be open");
+                        }
+                    }
+                }
+            }
+            .instrument(info_span!("downloader", id = task + 1)),
+        );
+        downloader_handles.push(handle);
+    }
+
+    drop(given_up_tx);
+
+    for handle in downloader_handles {
+        if let Err(err) = handle.await {
+            warn!("a downloader failed: {err}");
+        }
+    }
+
+    let mut given_up = Vec::new();
+    given_up_rx.recv_many(&mut given_up, total).await;
+    given_up.sort_unstable_by_key(|(track_num, _)| *track_num);
+
+    given_up
+}
+
+/// Download `track`, trying once, then retrying up to `retries` times.
+///
+/// Returns the path of the downloaded file, or `None` if every try failed.
+pub async fn download_one<D: Downloader>(
+    track: &Track,
+    retries: usize,
+    downloader: &D,
+) -> Option<PathBuf> {
+    for attempt in 0..=retries {
+        let options = DownloadOptions {
+            attempt,
+            ..Default::default()
+        };
+
+        match downloader.download(&track.url, options).await {
+            Ok(path) => return Some(path),
+            Err(err) => warn!("{err}"),
+        }
+    }
+
+    None
+}
+
+/// Log every line of `stderr` in `span`, as warnings if `warn`.
+fn redir_output(span: Span, stderr: ChildStderr, warn: bool) {
+    let mut stderr = BufReader::new(stderr).lines();
+
+    tokio::spawn(
+        async move {
+            while let Ok(Some(line)) = stderr.next_line().await {
+                log_output(&line, warn);
+            }
+        }
+        .instrument(span),
+    );
+}
+
+/// Log a line of a downloader's output, as a warning if `warn`.
+fn log_output(line: &str, warn: bool) {
+    let line = line.trim();
+    if line.is_empty() {
+        return;
+    }
+    if warn {
+        warn!("{line}");
+    } else {
+        info!("{line}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    use anyhow::anyhow;
+
+    use super::{DownloadOptions, Downloader, download_many};
+    use crate::spotify::{Metadata, Track};
+
+    /// Fails each url as many times as it is listed in `failures`.
+    struct Flaky {
+        failures: Mutex<HashMap<String, usize>>,
+        attempts: Mutex<Vec<String>>,
+    }
+
+    impl Downloader for Flaky {
+        async fn download(&self, url: &str, _options: DownloadOptions) -> anyhow::Result<PathBuf> {
+            self.attempts.lock().unwrap().push(url.to_string());
+
+            let mut failures = self.failures.lock().unwrap();
+            match failures.get_mut(url) {
+                Some(0) | None => Ok(PathBuf::from(url)),
+                Some(left) => {
+                    *left -= 1;
+                    Err(anyhow!("failed {url}"))
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_then_gives_up() {
+        let urls = ["ok", "flaky", "broken"];
+        let downloader = Arc::new(Flaky {
+            failures: Mutex::new(HashMap::from([
+                ("flaky".to_string(), 2),
+                ("broken".to_string(), usize::MAX),
+            ])),
+            attempts: Mutex::new(Vec::new()),
+        });
+
+        let (tracks_tx, tracks_rx) = async_channel::bounded(urls.len());
+        for (i, url) in urls.iter().enumerate() {
+            let track = Track::new((*url).to_string(), Metadata::default());
+            tracks_tx.send((i + 1, track)).await.unwrap();
+        }
+        drop(tracks_tx);
+
+        let (output_tx, output_rx) = async_channel::bounded(urls.len());
+        let given_up =
+            download_many(tracks_rx, urls.len(), 2, 3, downloader.clone(), output_tx).await;
+
+        let mut succeeded: Vec<_> = std::iter::from_fn(|| output_rx.try_recv().ok())
+            .map(|d| d.track_num)
+            .collect();
+        succeeded.sort_unstable();
+        assert_eq!(succeeded, [1, 2]);
+
+        assert_eq!(given_up.len(), 1);
+        assert_eq!(given_up[0].0, 3);
+
+        let attempts = downloader.attempts.lock().unwrap();
+        let count = |url| attempts.iter().filter(|a| *a == url).count();
+        assert_eq!(count("ok"), 1);
+        // the last of the 3 tries succeeds
+        assert_eq!(count("flaky"), 3);
+        assert_eq!(count("broken"), 3);
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use indicatif::{HumanBytes, HumanDuration, ProgressStyle};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+};
+use tracing::{Span, instrument};
+use tracing_indicatif::span_ext::IndicatifSpanExt;
+
+use super::{DownloadOptions, Downloader, log_output};
+
+/// Starts the progress lines we ask yt-dlp for, so they can be told apart from the printed file path.
+const PROGRESS_PREFIX: &str = "[sptfydl]";
+
+// https://github.com/yt-dlp/yt-dlp#output-template, missing fields are printed as `NA`
+const DOWNLOAD_TEMPLATE: &str = "download:[sptfydl] download %(progress.downloaded_bytes)s %(progress.total_bytes)s %(progress.total_bytes_estimate)s %(progress.speed)s %(progress.eta)s";
+const POSTPROCESS_TEMPLATE: &str =
+    "postprocess:[sptfydl] postprocess %(progress.status)s %(progress.postprocessor)s";
+
+/// Downloads with [yt-dlp](https://github.com/yt-dlp/yt-dlp).
+#[derive(Debug, Clone)]
+pub struct YtDlp {
+    program: PathBuf,
+    args: Vec<String>,
+    show_output: bool,
+}
+
+impl YtDlp {
+    /// Run the yt-dlp at `program`, passing `args` to every invocation. If `show_output`, yt-dlp is run verbosely and its output is logged as info instead of warnings.
+    #[must_use]
+    pub fn new(program: PathBuf, args: Vec<String>, show_output: bool) -> Self {
+        Self {
+            program,
+            args,
+            show_output,
+        }
+    }
+}
+
+/// Get the version of the yt-dlp at `program`.
+///
+/// # Errors
+///
+/// `program` could not be run, or exited unsuccessfully.
+pub async fn ytdlp_version(program: &Path) -> anyhow::Result<String> {
+    let version = version_output(program, "--version").await?;
+    Ok(version.trim().to_string())
+}
+
+/// Get the version of the ffmpeg yt-dlp would use, given yt-dlp's `--ffmpeg-location`.
+///
+/// # Errors
+///
+/// ffmpeg could not be run, or exited unsuccessfully.
+pub async fn ffmpeg_version(location: Option<&Path>) -> anyhow::Result<String> {
+    let version = version_output(&ffmpeg_program(location), "-version").await?;
+    // ffmpeg version 7.1 Copyright (c) ...
+    let version = version
+        .lines()
+        .next()
+        .and_then(|l| l.strip_prefix("ffmpeg version "))
+        .and_then(|l| l.split_whitespace().next())
+        .unwrap_or("unknown");
+
+    Ok(version.to_string())
+}
+
+/// The ffmpeg binary yt-dlp would use, given its `--ffmpeg-location`.
+#[must_use]
+pub fn ffmpeg_program(location: Option<&Path>) -> PathBuf {
+    // like yt-dlp, the location can be the binary or its directory
+    match location {
+        Some(dir) if dir.is_dir() => dir.join("ffmpeg"),
+        Some(program) => program.to_path_buf(),
+        None => PathBuf::from("ffmpeg"),
+    }
+}
+
+async fn version_output(program: &Path, arg: &str) -> anyhow::Result<String> {
+    let output = Command::new(program)
+        .arg(arg)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|err| anyhow!("failed to run {}: {err}", program.display()))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "`{} {arg}` terminated with {}",
+            program.display(),
+            output.status
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl Downloader for YtDlp {
+    #[instrument(name = "ytdlp", skip_all, fields(track = ?options.track_num, try = options.attempt + 1))]
+    async fn download(&self, url: &str, options: DownloadOptions) -> anyhow::Result<PathBuf> {
+        let mut ytdlp = Command::new(&self.program);
+        ytdlp.arg(url);
+        if let Some(template) = output_template(options) {
+            ytdlp.args(["-o", &template]);
+        }
+        if self.show_output {
+            ytdlp.arg("--verbose");
+        }
+        let ytdlp = ytdlp
+            .args(["-f", "ba"])
+            .args(["--quiet", "--print", "after_move:filepath"])
+            .args(["--progress", "--newline"])
+            .args(["--progress-template", DOWNLOAD_TEMPLATE])
+            .args(["--progress-template", POSTPROCESS_TEMPLATE])
+            .args(&self.args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut ytdlp = ytdlp.map_err(|err| anyhow!("failed to spawn yt-dlp: {err}"))?;
+
+        // progress is printed to stdout along with the file path, or to stderr once `--print` quiets yt-dlp
+        let stdout = ytdlp.stdout.take().expect("stdout is always captured");
+        let stderr = ytdlp.stderr.take().expect("stderr is always captured");
+        let mut view = ProgressView::default();
+        let path = read_output(stdout, stderr, &mut view, !self.show_output).await?;
+
+        let status = ytdlp.wait().await?;
+
+        if !status.success() {
+            return Err(anyhow!("yt-dlp terminated with {status}"));
+        }
+
+        let Some(path) = path else {
+            return Err(anyhow!("yt-dlp did not print the downloaded file"));
+        };
+
+        Ok(PathBuf::from(path))
+    }
+}
+
+/// Read yt-dlp's output until it exits, showing its progress on `view` and logging the rest of `stderr`,
+/// as warnings if `warn`.
+///
+/// Returns the last other line of `stdout`, the path of the downloaded file.
+async fn read_output(
+    stdout: impl AsyncRead + Unpin,
+    stderr: impl AsyncRead + Unpin,
+    view: &mut ProgressView,
+    warn: bool,
+) -> io::Result<Option<String>> {
+    let mut stdout = BufReader::new(stdout).lines();
+    let mut stderr = BufReader::new(stderr).lines();
+    let (mut stdout_open, mut stderr_open) = (true, true);
+
+    let mut path = None;
+    while stdout_open || stderr_open {
+        tokio::select! {
+            line = stdout.next_line(), if stdout_open => match line? {
+                Some(line) => match Progress::parse(&line) {
+                    Some(progress) => view.show(&Span::current(), &progress),
+                    None if !line.trim().is_empty() => path = Some(line),
+                    None => (),
+                },
+                None => stdout_open = false,
+            },
+            line = stderr.next_line(), if stderr_open => match line {
+                Ok(Some(line)) => match Progress::parse(&line) {
+                    Some(progress) => view.show(&Span::current(), &progress),
+                    None => log_output(&line, warn),
+                },
+                // like `redir_output`, unreadable output is not worth failing the download
+                _ => stderr_open = false,
+            },
+        }
+    }
+
+    Ok(path)
+}
+
+/// The yt-dlp output template to download with, if not yt-dlp's default (`%(title)s [%(id)s].%(ext)s`).
+fn output_template(options: DownloadOptions) -> Option<String> {
+    options
+        .track_prefix()
+        .map(|track| format!("{track} - %(title)s [%(id)s].%(ext)s"))
+}
+
+/// The name yt-dlp would give the file of the video `id` titled `title`, downloaded as `ext`,
+/// unless its args change the output template.
+///
+/// Only an approximation of how `--restrict-filenames` sanitizes the title.
+#[must_use]
+pub fn expected_file_name(title: &str, id: &str, ext: &str, options: DownloadOptions) -> String {
+    let mut sanitized = String::with_capacity(title.len());
+    for c in title.chars() {
+        match c {
+            '?' | '"' => (),
+            ':' => sanitized.push_str("_-"),
+            c if c.is_ascii_alphanumeric() || "-._+=@%~".contains(c) => sanitized.push(c),
+            _ => sanitized.push('_'),
+        }
+    }
+    let mut title = String::with_capacity(sanitized.len());
+    for c in sanitized.chars() {
+        if !(c == '_' && title.ends_with('_')) {
+            title.push(c);
+        }
+    }
+    let title = title.trim_matches('_');
+
+    output_template(options)
+        .unwrap_or_else(|| "%(title)s [%(id)s].%(ext)s".to_string())
+        .replace("%(title)s", title)
+        .replace("%(id)s", id)
+        .replace("%(ext)s", ext)
+}
+
+/// A progress line printed by yt-dlp.
+#[derive(Debug, PartialEq)]
+enum Progress {
+    Download {
+        downloaded: u64,
+        /// Falls back to yt-dlp's estimate.
+        total: Option<u64>,
+        /// In bytes per second.
+        speed: Option<u64>,
+        eta: Option<Duration>,
+    },
+    /// Conversion, embedding, moving the file, etc.
+    PostProcess { postprocessor: String },
+}
+
+impl Progress {
+    /// Parse a line printed with [`DOWNLOAD_TEMPLATE`] or [`POSTPROCESS_TEMPLATE`].
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line
+            .trim()
+            .strip_prefix(PROGRESS_PREFIX)?
+            .split_whitespace()
+            .collect();
+
+        match fields.as_slice() {
+            ["download", downloaded, total, estimate, speed, eta] => Some(Self::Download {
+                downloaded: parse_bytes(downloaded).unwrap_or(0),
+                total: parse_bytes(total).or_else(|| parse_bytes(estimate)),
+                speed: parse_bytes(speed),
+                eta: parse_number(eta).map(Duration::from_secs_f64),
+            }),
+            ["postprocess", _status, postprocessor] => Some(Self::PostProcess {
+                postprocessor: (*postprocessor).to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// `None` for `NA` and other non-numbers.
+fn parse_number(number: &str) -> Option<f64> {
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+}
+
+/// yt-dlp prints some byte counts, like estimates and speeds, as floats.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn parse_bytes(number: &str) -> Option<u64> {
+    parse_number(number).map(|n| n.round() as u64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Download,
+    PostProcess,
+}
+
+impl Phase {
+    fn style(self) -> ProgressStyle {
+        let template = match self {
+            Self::Download => {
+                "{span_child_prefix}{span_name}{{{span_fields}}} {bar:20} {bytes}/{total_bytes} {msg}"
+            }
+            Self::PostProcess => "{span_child_prefix}{spinner} {span_name}{{{span_fields}}} {msg}",
+        };
+        ProgressStyle::with_template(template).expect("valid template")
+    }
+}
+
+/// Shows yt-dlp's progress on a span's progress bar.
+#[derive(Debug, Default)]
+struct ProgressView {
+    phase: Option<Phase>,
+}
+
+impl ProgressView {
+    fn show(&mut self, span: &Span, progress: &Progress) {
+        match progress {
+            Progress::Download {
+                downloaded,
+                total,
+                speed,
+                eta,
+            } => {
+                self.enter(span, Phase::Download);
+                if let Some(total) = total {
+                    span.pb_set_length(*total);
+                }
+                span.pb_set_position(*downloaded);
+
+                let speed =
+                    speed.map_or_else(|| "?".to_string(), |s| format!("{}/s", HumanBytes(s)));
+                let eta = eta.map_or_else(|| "?".to_string(), |e| HumanDuration(e).to_string());
+                span.pb_set_message(&format!("{speed} (eta: {eta})"));
+            }
+            Progress::PostProcess { postprocessor } => {
+                self.enter(span, Phase::PostProcess);
+                span.pb_set_message(&format!("post-processing ({postprocessor})"));
+            }
+        }
+    }
+
+    fn enter(&mut self, span: &Span, phase: Phase) {
+        if self.phase != Some(phase) {
+            self.phase = Some(phase);
+            span.pb_set_style(&phase.style());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{DownloadOptions, Phase, Progress, ProgressView, expected_file_name, read_output};
+
+    #[test]
+    fn parse_progress() {
+        assert_eq!(
+            Progress::parse("[sptfydl] download 1024 NA 4096.5 512.2 3"),
+            Some(Progress::Download {
+                downloaded: 1024,
+                total: Some(4097),
+                speed: Some(512),
+                eta: Some(Duration::from_secs(3)),
+            })
+        );
+        assert_eq!(
+            Progress::parse("[sptfydl] download 0 2048 NA NA NA"),
+            Some(Progress::Download {
+                downloaded: 0,
+                total: Some(2048),
+                speed: None,
+                eta: None,
+            })
+        );
+        assert_eq!(
+            Progress::parse("[sptfydl] postprocess started ExtractAudio"),
+            Some(Progress::PostProcess {
+                postprocessor: "ExtractAudio".to_string()
+            })
+        );
+        assert_eq!(Progress::parse("/music/01 - a [xyz].opus"), None);
+    }
+
+    #[tokio::test]
+    async fn output_routing() {
+        // with `--print`, yt-dlp is quiet and its progress goes to stderr
+        let stdout = "/music/01 - a [xyz].opus\n";
+        let stderr = "[sptfydl] download 1024 2048 NA NA NA\n\
+                      WARNING: something\n\
+                      [sptfydl] postprocess started ExtractAudio\n";
+
+        let mut view = ProgressView::default();
+        let path = read_output(stdout.as_bytes(), stderr.as_bytes(), &mut view, true)
+            .await
+            .unwrap();
+
+        assert_eq!(path.as_deref(), Some("/music/01 - a [xyz].opus"));
+        assert_eq!(view.phase, Some(Phase::PostProcess));
+    }
+
+    #[test]
+    fn file_names() {
+        let options = DownloadOptions {
+            track_num: Some(3),
+            track_padding: 2,
+            attempt: 0,
+        };
+        assert_eq!(
+            expected_file_name("Song: A (Live)?", "abc", "mp3", options),
+            "03 - Song_-_A_Live [abc].mp3"
+        );
+        assert_eq!(
+            expected_file_name("AC/DC - T.N.T.", "abc", "opus", DownloadOptions::default()),
+            "AC_DC_-_T.N.T. [abc].opus"
+        );
+    }
+}
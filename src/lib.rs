@@ -8,10 +8,18 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use tracing::warn;
 
+pub mod download;
+pub mod lyrics;
+pub mod musicbrainz;
+pub mod network;
+pub mod playlist;
+pub mod secrets;
 pub mod spotify;
+pub mod tag;
 pub mod ytmusic;
 
-pub static CLIENT: LazyLock<Client> = LazyLock::new(Client::new);
+/// The client every request is sent with, configured with [`network::configure`].
+pub static CLIENT: LazyLock<Client> = LazyLock::new(network::client);
 
 /// Find the config directory, creating it if it doesn't exist. If all env vars are not found, defaults to `./`
 ///
@@ -122,7 +130,7 @@ mod tests {
 
     #[test]
     fn join() {
-        let list = vec!["1", "2", "3"];
+        let list = ["1", "2", "3"];
 
         assert_eq!(list.iter().join(","), "1,2,3");
         assert_eq!(list.iter().join(", "), "1, 2, 3");
@@ -0,0 +1,130 @@
+use anyhow::anyhow;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::{debug, instrument};
+
+use super::{Lyrics, LyricsProvider};
+use crate::{CLIENT, spotify::Metadata};
+
+/// The public [LRCLIB](https://lrclib.net) instance.
+pub const DEFAULT_URL: &str = "https://lrclib.net";
+
+/// How far a search result's duration can be from the track's, in seconds.
+const MAX_DURATION_DIFFERENCE: f64 = 2.0;
+
+const USER_AGENT: &str = concat!(
+    "sptfydl/",
+    env!("CARGO_PKG_VERSION"),
+    " (https://github.com/Trevrosa/sptfydl)"
+);
+
+/// Finds lyrics with an LRCLIB api.
+#[derive(Debug, Clone)]
+pub struct Lrclib {
+    base_url: String,
+}
+
+impl Lrclib {
+    /// Use the LRCLIB api at `base_url`, like [`DEFAULT_URL`].
+    #[must_use]
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let mut base_url = base_url.into();
+        while base_url.ends_with('/') {
+            base_url.pop();
+        }
+        Self { base_url }
+    }
+
+    async fn get<T: for<'a> Deserialize<'a>>(
+        &self,
+        endpoint: &str,
+        query: &[(&str, &str)],
+    ) -> anyhow::Result<Option<T>> {
+        let resp = CLIENT
+            .get(format!("{}{endpoint}", self.base_url))
+            .header("User-Agent", USER_AGENT)
+            .query(query)
+            .send()
+            .await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            return Err(anyhow!("got {}: {:?}", resp.status(), resp.text().await));
+        }
+
+        Ok(Some(resp.json().await?))
+    }
+}
+
+/// <https://lrclib.net/docs>
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    #[serde(default)]
+    duration: f64,
+    #[serde(default)]
+    instrumental: bool,
+    plain_lyrics: Option<String>,
+    synced_lyrics: Option<String>,
+}
+
+impl From<Record> for Option<Lyrics> {
+    fn from(record: Record) -> Self {
+        if record.instrumental || (record.plain_lyrics.is_none() && record.synced_lyrics.is_none())
+        {
+            None
+        } else {
+            Some(Lyrics {
+                plain: record.plain_lyrics,
+                synced: record.synced_lyrics,
+            })
+        }
+    }
+}
+
+impl LyricsProvider for Lrclib {
+    #[instrument(name = "lrclib", skip_all, fields(track = metadata.name))]
+    async fn lyrics(&self, metadata: &Metadata) -> anyhow::Result<Option<Lyrics>> {
+        let artist = metadata.artists.first().map_or("", |a| a.name.as_str());
+        let duration = metadata.duration_ms.div_ceil(1000).to_string();
+
+        let mut query = vec![
+            ("track_name", metadata.name.as_str()),
+            ("artist_name", artist),
+            ("duration", duration.as_str()),
+        ];
+        if let Some(album) = metadata.album_name.as_deref() {
+            query.push(("album_name", album));
+        }
+
+        if let Some(record) = self.get::<Record>("/api/get", &query).await? {
+            debug!("found exact match");
+            return Ok(record.into());
+        }
+
+        // the exact lookup needs every field to match, so fall back to the closest search result
+        let query = [
+            ("track_name", metadata.name.as_str()),
+            ("artist_name", artist),
+        ];
+        let results = self
+            .get::<Vec<Record>>("/api/search", &query)
+            .await?
+            .unwrap_or_default();
+        debug!("got {} search results", results.len());
+
+        let duration = f64::from(metadata.duration_ms) / 1000.0;
+        let closest = results
+            .into_iter()
+            .filter(|r| (r.duration - duration).abs() <= MAX_DURATION_DIFFERENCE)
+            .min_by(|a, b| {
+                let a = (a.duration - duration).abs();
+                let b = (b.duration - duration).abs();
+                a.total_cmp(&b)
+            });
+
+        Ok(closest.and_then(Into::into))
+    }
+}
@@ -0,0 +1,50 @@
+pub mod lrclib;
+
+pub use lrclib::Lrclib;
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::spotify::Metadata;
+
+/// The lyrics of a track.
+#[derive(Debug, Clone, Default)]
+pub struct Lyrics {
+    pub plain: Option<String>,
+    /// In the LRC format.
+    pub synced: Option<String>,
+}
+
+impl Lyrics {
+    /// The lyrics to embed in a tag: synced if there are any, since players that don't support them still show the text.
+    #[must_use]
+    pub fn preferred(&self) -> Option<&str> {
+        self.synced.as_deref().or(self.plain.as_deref())
+    }
+}
+
+/// A way to find the lyrics of a track.
+pub trait LyricsProvider: Send + Sync {
+    /// Find the lyrics of the track described by `metadata`.
+    ///
+    /// Returns `None` if the track has no lyrics, or could not be found.
+    fn lyrics(
+        &self,
+        metadata: &Metadata,
+    ) -> impl Future<Output = anyhow::Result<Option<Lyrics>>> + Send;
+}
+
+/// Write `synced` lyrics next to `track`, as an `.lrc` file with the same name.
+///
+/// Returns the path written to.
+///
+/// # Errors
+///
+/// See [`fs::write`].
+pub fn write_lrc(track: &Path, synced: &str) -> io::Result<PathBuf> {
+    let path = track.with_extension("lrc");
+    fs::write(&path, synced)?;
+    Ok(path)
+}
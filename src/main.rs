@@ -1,35 +1,33 @@
-use std::{
-    path::{Path, PathBuf},
-    process::{Stdio, exit},
-    sync::Arc,
-    time::Instant,
-};
+use std::{path::PathBuf, process::exit, time::Duration};
 
 use anyhow::Context;
-use clap::{ArgAction, Parser, ValueEnum};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use console::Term;
-use dialoguer::{Input, Password};
-use indicatif::{HumanDuration, ProgressStyle};
-use lofty::{
-    config::WriteOptions,
-    file::{AudioFile, TaggedFileExt},
-    picture::{MimeType, Picture, PictureType},
-    probe::Probe,
-    tag::{Accessor, ItemKey, Tag, items::Timestamp},
-};
-use serde::{Deserialize, Serialize};
-use tokio::{
-    io::{AsyncBufReadExt, BufReader},
-    process::{ChildStderr, Command},
-    sync::mpsc,
-};
-use tracing::{Instrument, Level, Span, debug, info, info_span, instrument, warn};
-use tracing_indicatif::{IndicatifLayer, span_ext::IndicatifSpanExt};
+use indicatif::ProgressStyle;
+use tracing::{Level, warn};
+use tracing_indicatif::IndicatifLayer;
 use tracing_subscriber::{filter::Targets, fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
 use sptfydl::{
-    CLIENT, load, save,
-    spotify::{Metadata, Track, extract_spotify, search::REQUESTS},
+    lyrics::lrclib,
+    musicbrainz,
+    network::{self, IpFamily, Network},
+    secrets,
+    spotify::{DEFAULT_ACCOUNT, validate_account},
+};
+
+mod cli;
+
+use cli::{
+    auth::auth,
+    cache::cache,
+    config::{load_config, parse_args},
+    credentials::passphrase,
+    download::download,
+    info::print_info,
+    retag::retag,
+    search::print_candidates,
+    sync::sync,
 };
 
 #[allow(unused)]
@@ -37,496 +35,470 @@ use sptfydl::{
 enum Format {
     Mp3,
     Flac,
+    Opus,
+    /// AAC in an m4a container
+    M4a,
+    /// Ogg Vorbis
+    Vorbis,
+    /// ALAC in an m4a container
+    Alac,
+    Wav,
+    /// Whatever yt-dlp finds best, without converting
     Original,
 }
 
-#[allow(clippy::struct_excessive_bools, clippy::struct_field_names)]
-#[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct Args {
-    /// The spotify url to download.
-    url: String,
+impl Format {
+    /// The `--audio-format` to convert to with yt-dlp, if any.
+    fn ytdlp_audio_format(self) -> Option<&'static str> {
+        match self {
+            Self::Mp3 => Some("mp3"),
+            Self::Flac => Some("flac"),
+            Self::Opus => Some("opus"),
+            Self::M4a => Some("m4a"),
+            Self::Vorbis => Some("vorbis"),
+            Self::Alac => Some("alac"),
+            Self::Wav => Some("wav"),
+            Self::Original => None,
+        }
+    }
 
-    /// The format to download songs to.
-    #[arg(short, long, value_enum, default_value_t = Format::Mp3)]
-    format: Format,
+    /// The extension of files downloaded as this format, if it's known before downloading.
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            Self::Mp3 => Some("mp3"),
+            Self::Flac => Some("flac"),
+            Self::Opus => Some("opus"),
+            Self::M4a | Self::Alac => Some("m4a"),
+            Self::Vorbis => Some("ogg"),
+            Self::Wav => Some("wav"),
+            Self::Original => None,
+        }
+    }
 
-    /// The path to output to.
-    #[arg(short = 'P', long)]
-    path: Option<PathBuf>,
+    /// Whether files of this format can always be tagged.
+    fn taggable(self) -> bool {
+        self != Self::Original
+    }
+}
 
-    /// The number of concurrent downloads.
-    #[arg(short, long, default_value_t = 5)]
-    downloaders: usize,
+/// Parse a spotify account name, which is used in file names.
+fn parse_account(account: &str) -> Result<String, String> {
+    validate_account(account)
+        .map(|()| account.to_string())
+        .map_err(|err| err.to_string())
+}
 
-    /// The number of concurrent searches.
-    #[arg(short, long, default_value_t = 3)]
-    searchers: usize,
+/// Parse a yt-dlp `--audio-quality`: a VBR level from 0 (best) to 10, or a bitrate like `192K`.
+fn parse_audio_quality(quality: &str) -> Result<String, String> {
+    let vbr = quality.parse::<u8>().is_ok_and(|q| q <= 10);
+    let bitrate = quality
+        .strip_suffix(['k', 'K'])
+        .is_some_and(|b| b.parse::<u16>().is_ok_and(|b| b > 0));
 
-    /// Prefer isrc for searches. Useful for when you want a specific recording of a song.
-    #[arg(long)]
-    isrc: bool,
+    if vbr || bitrate {
+        Ok(quality.to_string())
+    } else {
+        Err("expected a VBR level from 0 to 10, or a bitrate like 192K".to_string())
+    }
+}
 
-    /// Disable tagging of mp3 files.
-    #[arg(long)]
-    no_metadata: bool,
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    subcommand_negates_reqs = true,
+    override_usage = "sptfydl [OPTIONS] <URL> [-- <YTDLP_ARGS>...]\n       sptfydl [OPTIONS] <COMMAND>"
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Skip prompts; always choose the default or first available option.
-    #[arg(short, long)]
-    no_interaction: bool,
+    // without a subcommand, downloads like `download`
+    #[command(flatten)]
+    download: DownloadArgs,
 
-    /// The number of retries allowed for downloads.
-    #[arg(long, default_value_t = 5)]
-    download_retries: usize,
+    #[command(flatten)]
+    common: CommonArgs,
+}
 
-    /// The number of retries allowed for searches.
-    #[arg(long, default_value_t = 3)]
-    search_retries: usize,
+// The options every subcommand takes.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(clap::Args, Debug)]
+struct CommonArgs {
+    /// Use the options of this profile from config.yaml.
+    #[arg(long, global = true)]
+    profile: Option<String>,
 
-    /// Show the output of ytdlp commands.
-    #[arg(long)]
-    show_ytdlp: bool,
+    /// Skip prompts; always choose the default or first available option.
+    #[arg(short, long, global = true)]
+    no_interaction: bool,
 
     /// Be a bit more verbose. Can be applied more than once (-v, -vv)
-    #[arg(short, long, action = ArgAction::Count)]
+    #[arg(short, long, action = ArgAction::Count, global = true)]
     verbose: u8,
 
-    /// Additional args for yt-dlp.
-    #[arg(last = true)]
-    ytdlp_args: Vec<String>,
-}
+    /// The saved spotify account (credentials and token) to use. See `auth spotify`.
+    #[arg(long, value_name = "NAME", default_value = DEFAULT_ACCOUNT, value_parser = parse_account, global = true)]
+    account: String,
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+    /// When the spotify account is rate limited, switch to the next saved one, in turn.
+    #[arg(long, global = true)]
+    rotate_accounts: bool,
 
-    let filter = match args.verbose {
-        0 => Level::INFO,
-        1 => Level::DEBUG,
-        2..=u8::MAX => Level::TRACE,
-    };
+    /// A yaml file with the spotify `client_id` and `client_secret` to use, instead of the saved ones.
+    #[arg(long, value_name = "PATH", global = true)]
+    spotify_oauth_file: Option<PathBuf>,
 
-    let indicatif_layer = IndicatifLayer::new().with_max_progress_bars(
-        11, // 5 downloaders default, + 5 for each ytdlp subspan, + 1 for progress bar
-        Some(
-            ProgressStyle::with_template("...and {pending_progress_bars} more not shown above.")
-                .expect("valid template"),
-        ),
-    );
+    /// A cookies.txt, browser.json or request headers with the ytmusic cookie to use, instead of the saved one.
+    #[arg(long, value_name = "PATH", global = true)]
+    ytm_cookie_file: Option<PathBuf>,
 
-    tracing_subscriber::registry()
-        .with(
-            fmt::layer()
-                .without_time()
-                .compact()
-                .with_writer(indicatif_layer.get_stderr_writer()),
-        )
-        .with(Targets::new().with_target("sptfydl", filter))
-        .with(indicatif_layer)
-        .init();
+    /// Encrypt saved credentials and tokens with the passphrase in this file. Also read from `SPTFYDL_PASSPHRASE`.
+    #[arg(long, value_name = "PATH", global = true)]
+    passphrase_file: Option<PathBuf>,
 
-    ctrlc::set_handler(handle_exit)?;
+    /// The ffmpeg binary (or its directory) for yt-dlp to use, if it's not in PATH.
+    #[arg(long, value_name = "PATH", global = true)]
+    ffmpeg_location: Option<PathBuf>,
 
-    let mut ytdlp_args = args.ytdlp_args;
+    /// Send every request through this http, https or socks5 proxy, like `socks5://127.0.0.1:1080`.
+    /// Also given to yt-dlp.
+    #[arg(long, value_name = "URL", global = true)]
+    proxy: Option<String>,
 
-    ytdlp_args.push("--no-playlist".to_string());
-    ytdlp_args.push("--restrict-filenames".to_string());
+    /// Give up connecting after this many seconds.
+    #[arg(long, value_name = "SECS", default_value_t = network::DEFAULT_CONNECT_TIMEOUT.as_secs(), global = true)]
+    connect_timeout: u64,
 
-    match args.format {
-        Format::Mp3 | Format::Flac => ytdlp_args.push("--extract-audio".to_string()),
-        Format::Original => (),
-    }
+    /// Give up on a response after waiting this many seconds for more of it. Also given to yt-dlp.
+    #[arg(long, value_name = "SECS", default_value_t = network::DEFAULT_READ_TIMEOUT.as_secs(), global = true)]
+    read_timeout: u64,
 
-    match args.format {
-        Format::Mp3 => ytdlp_args.extend(["--audio-format", "mp3"].map(ToString::to_string)),
-        Format::Flac => ytdlp_args.extend(["--audio-format", "flac"].map(ToString::to_string)),
-        Format::Original => (),
-    }
+    /// Send this user agent to spotify, ytmusic and cover hosts.
+    #[arg(long, global = true)]
+    user_agent: Option<String>,
 
-    let oauth = get_spotify_oauth()?;
-
-    let start = Instant::now();
-    let extraction = extract_spotify(
-        &oauth.client_id,
-        &oauth.client_secret,
-        &args.url,
-        args.searchers,
-        args.no_interaction,
-        args.search_retries,
-        args.isrc,
-    )
-    .await
-    .context("extracting youtube urls from spotify")?;
-    let search_time = start.elapsed();
-
-    if let Some(path) = args.path {
-        ytdlp_args.extend(["-P".to_string(), path.to_string_lossy().into_owned()]);
-    } else if let Some(path) = extraction.name.as_ref() {
-        ytdlp_args.extend(["-P".to_string(), path.replace('/', "_")]);
-    }
+    /// Only connect over IPv4. Also given to yt-dlp.
+    #[arg(long, conflicts_with = "force_ipv6", global = true)]
+    force_ipv4: bool,
 
-    let start = Instant::now();
-    if extraction.tracks.len() == 1 {
-        download_one(
-            extraction.tracks[0].1.clone(),
-            &ytdlp_args,
-            args.download_retries,
-            args.show_ytdlp,
-            !args.no_metadata,
-            args.format == Format::Mp3,
-        )
-        .await;
-    } else {
-        download_many(
-            extraction.tracks.clone(),
-            Arc::from(ytdlp_args),
-            args.downloaders,
-            args.download_retries,
-            args.show_ytdlp,
-            !args.no_metadata,
-            args.format == Format::Mp3,
-        )
-        .await;
-    }
-    let download_time = start.elapsed();
+    /// Only connect over IPv6. Also given to yt-dlp.
+    #[arg(long, global = true)]
+    force_ipv6: bool,
+}
 
-    info!(
-        "took {} to download {} tracks ({search_time:?} to search, {download_time:?} to download)",
-        HumanDuration(search_time + download_time),
-        extraction.tracks.len()
-    );
+// How to search ytmusic.
+#[derive(clap::Args, Debug)]
+struct SearchingArgs {
+    /// Prefer isrc for searches. Useful for when you want a specific recording of a song.
+    #[arg(long)]
+    isrc: bool,
 
-    info!("used {REQUESTS:?} spotify api calls in total");
+    /// The number of retries allowed for searches.
+    #[arg(long, default_value_t = 3)]
+    search_retries: usize,
+}
 
-    if !extraction.warnings.is_empty() {
-        warn!(
-            "these tracks could be incorrect: {:#?}",
-            extraction.warnings()
-        );
-    }
+// How to tag files.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(clap::Args, Debug)]
+struct TagArgs {
+    /// The number of concurrent taggers.
+    #[arg(short, long, default_value_t = 2)]
+    taggers: usize,
 
-    if extraction.failures > 0 {
-        warn!(
-            "{} songs failed to search, check report named `failed-...txt`",
-            extraction.failures
-        );
-    }
+    /// Join multiple artists and genres with this, instead of writing them as separate values.
+    /// For players that don't support multi-value tags.
+    #[arg(long, value_name = "SEPARATOR")]
+    tag_separator: Option<String>,
+
+    /// Don't write a comment with the spotify and youtube urls. They are still written to their own tags.
+    #[arg(long)]
+    no_comment: bool,
+
+    /// Find lyrics with LRCLIB, and embed them.
+    #[arg(long)]
+    lyrics: bool,
+
+    /// Also write synced lyrics to .lrc files next to each track. Implies --lyrics.
+    #[arg(long)]
+    lrc: bool,
+
+    /// The LRCLIB api to find lyrics with.
+    #[arg(long, value_name = "URL", default_value = lrclib::DEFAULT_URL)]
+    lyrics_url: String,
+
+    /// Look up each track's isrc and album upc on musicbrainz, and write the recording, release and artist ids.
+    /// Lookups are limited to one per second.
+    #[arg(long)]
+    musicbrainz: bool,
+
+    /// The musicbrainz api to look up ids with.
+    #[arg(long, value_name = "URL", default_value = musicbrainz::DEFAULT_URL)]
+    musicbrainz_url: String,
+
+    /// Keep downloaded covers in the config directory, to reuse them in later runs.
+    #[arg(long)]
+    cache_covers: bool,
+
+    /// Scale embedded covers down to fit in this many pixels, re-encoding them as jpeg. Needs ffmpeg.
+    #[arg(long, value_name = "PIXELS")]
+    cover_max_size: Option<u32>,
 
-    Ok(())
+    /// Measure each track's loudness with ffmpeg, and write replaygain tags.
+    /// When a whole album is downloaded, its gain is written too, once every track is measured.
+    #[arg(long)]
+    replaygain: bool,
 }
 
-async fn download_many(
-    urls: Vec<(usize, Track)>,
-    args: Arc<[String]>,
-    downloaders: usize,
-    retry_limit: usize,
-    show_ytdlp: bool,
-    tag_metadata: bool,
-    mp3: bool,
-) {
-    let urls_len = urls.len();
+#[allow(clippy::struct_excessive_bools)]
+#[derive(clap::Args, Debug)]
+struct DownloadArgs {
+    /// The spotify url to download.
+    #[arg(required = true)]
+    url: Option<String>,
 
-    let (tracks_tx, tracks_rx) = async_channel::bounded(downloaders);
-    // we dont want this channel to block on `send`s
-    let (failed_tx, failed_rx) = async_channel::bounded(urls_len);
-    let (results_tx, mut results_rx) = mpsc::channel(urls_len);
+    /// The format to download songs to.
+    #[arg(short, long, value_enum, default_value_t = Format::Mp3)]
+    format: Format,
 
-    let track_padding = urls.last().unwrap().0.to_string().len();
+    /// The quality to convert to: a VBR level from 0 (best) to 10 (worst), or a bitrate like 192K.
+    /// Ignored for lossless formats.
+    #[arg(short = 'q', long, value_parser = parse_audio_quality)]
+    audio_quality: Option<String>,
 
-    tokio::spawn(async move {
-        for url in urls {
-            tracks_tx.send(url).await.expect("channel should be open");
-        }
-    });
+    /// The path to output to.
+    #[arg(short = 'P', long)]
+    path: Option<PathBuf>,
 
-    let pb_span = info_span!("pb");
+    /// The number of concurrent downloads.
+    #[arg(short, long, default_value_t = 5)]
+    downloaders: usize,
 
-    pb_span.pb_set_style(
-        &ProgressStyle::with_template("{wide_bar} {pos}/{len} ({elapsed}) (eta: {eta})")
-            .expect("valid template"),
-    );
-    pb_span.pb_set_length(urls_len as u64);
-
-    pb_span.pb_start();
-
-    let mut downloader_handles = Vec::with_capacity(downloaders);
-    for task in 0..downloaders {
-        let failed_tx = failed_tx.clone();
-        let failed_rx = failed_rx.clone();
-        let tracks = tracks_rx.clone();
-        let results = results_tx.clone();
-        let args = args.clone();
-
-        let handle = tokio::spawn(
-            async move {
-                loop {
-                    debug!("waiting for url");
-
-                    // `urls_tx` will be dropped once all urls are sent,
-                    // closing the channel, meaning that eventually
-                    // `recv()` will return an error, letting the task end.
-                    //
-                    // conversely, the `failed` channel has multiple cloned senders,
-                    // meaning the channel will not close until all tasks end:
-                    // using `try_recv()` ensures that the task will end instead of waiting forever.
-                    let result = match tracks.recv().await {
-                        Ok((i, url)) => Ok((0, i, url)),
-                        Err(_) => failed_rx.try_recv(),
-                    };
-
-                    let Ok((retry, track_num, track)) = result else {
-                        debug!("no more urls");
-                        return;
-                    };
-
-                    let Track { url, metadata } = track;
-
-                    if retry > retry_limit.saturating_sub(1) {
-                        warn!("track {track_num}: {url} reached retry limit");
-                        continue;
-                    }
-
-                    info!("track {track_num}: {url}");
-                    let (output_file, url) = ytdlp(
-                        url,
-                        Some(track_num),
-                        retry,
-                        track_padding,
-                        show_ytdlp,
-                        &args,
-                    )
-                    .await;
-                    results
-                        .send(output_file.is_some())
-                        .await
-                        .expect("shouldnt be closed");
-
-                    if let Some(path) = output_file {
-                        run_tagger(path.as_ref(), metadata, &url, tag_metadata, mp3).await;
-                    } else {
-                        failed_tx
-                            .send((retry + 1, track_num, Track::new(url, metadata)))
-                            .await
-                            .expect("channel should be open");
-                    }
-                }
-            }
-            .instrument(info_span!("downloader", id = task + 1)),
-        );
-        downloader_handles.push(handle);
-    }
+    /// The number of concurrent searches.
+    #[arg(short, long, default_value_t = 3)]
+    searchers: usize,
 
-    drop(results_tx);
+    #[command(flatten)]
+    searching: SearchingArgs,
 
-    async {
-        while let Some(success) = results_rx.recv().await {
-            if success {
-                Span::current().pb_inc(1);
-            }
-        }
-    }
-    .instrument(pb_span)
-    .await;
+    /// Disable tagging of downloaded files.
+    #[arg(long)]
+    no_metadata: bool,
 
-    for handle in downloader_handles {
-        if let Err(err) = handle.await {
-            warn!("a downloader failed: {err}");
-        }
-    }
-}
+    #[command(flatten)]
+    tagging: TagArgs,
 
-async fn download_one(
-    track: Track,
-    ytdlp_args: &[String],
-    retry_limit: usize,
-    show_ytdlp: bool,
-    should_tag: bool,
-    mp3: bool,
-) {
-    let Track { mut url, metadata } = track;
-    info!("downloading {url}");
-    for attempt in 0..=retry_limit {
-        let (output_file, new_url) = ytdlp(url, None, attempt, 0, show_ytdlp, ytdlp_args).await;
-
-        url = new_url;
-
-        if let Some(path) = output_file {
-            run_tagger(path.as_ref(), metadata, &url, should_tag, mp3).await;
-            break;
-        }
-    }
-}
+    /// When downloading an album, also save its cover next to its tracks with this name, like cover.jpg (the extension follows the image's format).
+    #[arg(long, value_name = "NAME")]
+    cover_file: Option<String>,
 
-/// returns a (`output_file`, `url`). `output_file` will always be `Some` on success.
-#[inline]
-#[instrument(skip(url, args, retry, track_padding, show_output), fields(try = retry + 1))]
-async fn ytdlp(
-    url: String,
-    track: Option<usize>,
-    retry: usize,
-    track_padding: usize,
-    show_output: bool,
-    args: &[String],
-) -> (Option<String>, String) {
-    let mut ytdlp = Command::new("yt-dlp");
-    ytdlp.arg(&url);
-    if let Some(track) = track {
-        // yt-dlp output template
-        ytdlp.args([
-            "-o",
-            &format!("{track:0>track_padding$} - %(title)s [%(id)s].%(ext)s"),
-        ]);
-    }
-    if show_output {
-        ytdlp.arg("--verbose");
-    }
-    let ytdlp = ytdlp
-        .args(["-f", "ba"])
-        .args(["--quiet", "--print", "after_move:filepath"])
-        .args(args)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn();
-    let Ok(mut ytdlp) = ytdlp else {
-        warn!("failed to spawn yt-dlp");
-        return (None, url);
-    };
+    /// The number of retries allowed for downloads.
+    #[arg(long, default_value_t = 5)]
+    download_retries: usize,
 
-    redir_output(
-        Span::current(),
-        ytdlp.stderr.take().expect("stderr is always captured"),
-        !show_output,
-    );
+    /// Don't write an .m3u8 playlist file when downloading a playlist or album.
+    #[arg(long)]
+    no_m3u8: bool,
 
-    let result = ytdlp.wait_with_output().await;
+    /// Also write an .xspf playlist file when downloading a playlist or album.
+    #[arg(long)]
+    xspf: bool,
 
-    if let Ok(result) = result {
-        let status = result.status;
+    /// Download with this program instead of yt-dlp. It gets the args after `--` and the url,
+    /// and should print the path of the downloaded file. See `SPTFYDL_OUTPUT_DIR` and `SPTFYDL_TRACK`.
+    #[arg(long, value_name = "PROGRAM")]
+    download_command: Option<PathBuf>,
 
-        if status.success() {
-            let path = String::from_utf8(result.stdout[0..result.stdout.len() - 1].to_vec())
-                .expect("should be utf8");
-            return (Some(path), url);
-        }
+    /// The yt-dlp binary to use, if it's not in PATH.
+    #[arg(long, value_name = "PATH")]
+    ytdlp_path: Option<PathBuf>,
 
-        warn!("yt-dlp terminated with {status}");
-    }
+    /// Show the output of ytdlp commands.
+    #[arg(long)]
+    show_ytdlp: bool,
 
-    (None, url)
+    /// Search for every track, then print what would be downloaded and an estimate of where, without downloading anything.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Additional args for yt-dlp (or the download command).
+    #[arg(last = true)]
+    ytdlp_args: Vec<String>,
 }
 
-/// only warns if user set --mp3, but still tag in case user converts file to a different but supported format.
-async fn run_tagger(path: &Path, metadata: Metadata, url: &str, should_tag: bool, mp3: bool) {
-    if should_tag
-        && let Err(err) = tagger(path, metadata, url).await
-        && mp3
-    {
-        warn!("failed to tag file {path:?}: {err}");
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Download a spotify track, album or playlist. The same as giving no subcommand.
+    Download(DownloadArgs),
+    /// Print the tracks of a spotify url, without searching or downloading them.
+    Info {
+        /// The spotify url to show.
+        url: String,
+    },
+    /// Search ytmusic for the tracks of a spotify url, and print the results for each, without downloading them.
+    Search(SearchArgs),
+    /// Download only the tracks of a playlist or album that are missing from its directory,
+    /// and rewrite its playlist files.
+    Sync(SyncArgs),
+    /// Rewrite the tags of already downloaded files with fresh metadata, without downloading them again.
+    Retag(RetagArgs),
+    /// Show, set or remove the saved spotify and ytmusic credentials.
+    #[command(subcommand)]
+    Auth(AuthCommand),
+    /// Show or clear the cached covers and spotify tokens.
+    #[command(subcommand)]
+    Cache(CacheCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum AuthCommand {
+    /// Show which credentials are saved, and when the spotify token expires.
+    Status,
+    /// Set the spotify client id and secret of `--account`, checking them by requesting a token.
+    Spotify,
+    /// Set the ytmusic cookie, checking that it is logged in.
+    Ytm,
+    /// Remove the saved ytmusic cookie, and the credentials and token of `--account`.
+    Logout {
+        /// Only remove the credentials of these services.
+        #[arg(value_enum)]
+        services: Vec<Service>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Service {
+    Spotify,
+    Ytm,
 }
 
-// TODO: maybe this should be changed to specific tag impls because different tags have different ways of representing multi-value tags
-#[instrument(skip(metadata, url))]
-async fn tagger(path: &Path, metadata: Metadata, url: &str) -> anyhow::Result<()> {
-    let mut file = Probe::open(path)?.guess_file_type()?.read()?;
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Show how many covers and spotify tokens are cached.
+    Info,
+    /// Remove the cached covers and spotify tokens.
+    Clear {
+        /// Only clear these caches.
+        #[arg(value_enum)]
+        caches: Vec<Cache>,
+    },
+}
 
-    debug!("tagging file");
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Cache {
+    Covers,
+    Tokens,
+}
 
-    let mut tag = Tag::new(file.primary_tag_type());
+#[derive(clap::Args, Debug)]
+struct SearchArgs {
+    /// The spotify url to search for.
+    url: String,
 
-    // album & cover
-    if let Some(album_name) = metadata.album_name {
-        tag.set_album(album_name);
-    }
-    if let Some(cover_url) = metadata.cover_url {
-        let cover = CLIENT.get(cover_url).send().await?;
-        let mime_type: Option<MimeType> = cover
-            .headers()
-            .get("content-type")
-            .iter()
-            .find_map(|h| h.to_str().map(MimeType::from_str).ok());
-        let image = cover.bytes().await?;
-
-        let mut picture = Picture::unchecked(image.to_vec()).pic_type(PictureType::CoverFront);
-        if let Some(mime_type) = mime_type {
-            picture = picture.mime_type(mime_type);
-        }
-        tag.push_picture(picture.build());
-    }
+    /// The number of results to print for each track.
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    results: usize,
 
-    // artists & genres
-    let (artists, genres) = Metadata::to_tag_values(metadata.artists, "; ");
-    tag.set_artist(artists);
-    tag.set_genre(genres);
+    #[command(flatten)]
+    searching: SearchingArgs,
+}
 
-    // all other
-    tag.set_title(metadata.name);
-    tag.set_disk(metadata.disc_number);
+#[derive(clap::Args, Debug)]
+struct SyncArgs {
+    #[command(flatten)]
+    download: DownloadArgs,
 
-    if metadata.explicit {
-        // 1 is explicit
-        tag.insert_text(ItemKey::ParentalAdvisory, "1".to_string());
-    }
+    /// Also delete the files of tracks that are no longer in the playlist or album.
+    #[arg(long)]
+    delete: bool,
+}
 
-    if let Some(isrc) = metadata.external_ids.isrc {
-        tag.insert_text(ItemKey::Isrc, isrc);
-    }
+#[derive(clap::Args, Debug)]
+struct RetagArgs {
+    /// The directory to retag, including its subdirectories.
+    dir: PathBuf,
 
-    if let Some(date) = metadata.release_date.as_deref() {
-        // note y-m-d
-        let date: Vec<u16> = date.split('-').flat_map(str::parse::<u16>).collect();
-        tag.set_date(Timestamp {
-            year: date[0],
-            month: u8::try_from(date[1]).ok(),
-            day: u8::try_from(date[2]).ok(),
-            ..Default::default()
-        });
-    }
+    /// A yaml file mapping file paths (relative to DIR) to spotify track urls or ids,
+    /// for files without an embedded track id.
+    #[arg(long, value_name = "FILE")]
+    map: Option<PathBuf>,
 
-    tag.set_track(metadata.track_number);
-    if let Some(album_tracks) = metadata.album_tracks {
-        tag.set_track_total(album_tracks);
-    }
+    #[command(flatten)]
+    tagging: TagArgs,
+}
 
-    tag.set_comment(format!(
-        r"
-    original spotify url: https://open.spotify.com/track/{}
-    downloaded from: {url}
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (args, tagging) = parse_args(load_config()?)?;
+    let common = &args.common;
 
-    by sptfydl!
-    ",
-        metadata.spotify_id
-    ));
+    init_tracing(common.verbose);
 
-    file.insert_tag(tag);
+    ctrlc::set_handler(handle_exit)?;
 
-    let start = Instant::now();
-    // TODO: measure time with spawn_blocking and none
-    file.save_to_path(path, WriteOptions::default())?;
-    debug!("save took {:?}", start.elapsed());
+    if let Some(passphrase) = passphrase(common.passphrase_file.as_deref())? {
+        secrets::set_passphrase(passphrase);
+    }
+    network::configure(network_options(common)).context("configuring the network")?;
+
+    match &args.command {
+        None => download(&args.download, common, tagging).await,
+        Some(Command::Download(download_args)) => download(download_args, common, tagging).await,
+        Some(Command::Info { url }) => print_info(url, common).await,
+        Some(Command::Search(search_args)) => print_candidates(search_args, common).await,
+        Some(Command::Sync(sync_args)) => sync(sync_args, common, tagging).await,
+        Some(Command::Retag(retag_args)) => retag(retag_args, common, tagging).await,
+        Some(Command::Auth(command)) => auth(command, common).await,
+        Some(Command::Cache(command)) => cache(command),
+    }
+}
 
-    Ok(())
+/// How to connect, according to `args`.
+fn network_options(args: &CommonArgs) -> Network {
+    let ip_family = if args.force_ipv4 {
+        Some(IpFamily::V4)
+    } else if args.force_ipv6 {
+        Some(IpFamily::V6)
+    } else {
+        None
+    };
+    Network {
+        proxy: args.proxy.clone(),
+        connect_timeout: Duration::from_secs(args.connect_timeout),
+        read_timeout: Duration::from_secs(args.read_timeout),
+        user_agent: args.user_agent.clone(),
+        ip_family,
+    }
 }
 
-fn redir_output(span: Span, stderr: ChildStderr, warn: bool) {
-    let mut stderr = BufReader::new(stderr).lines();
-
-    tokio::spawn(
-        async move {
-            while let Ok(Some(line)) = stderr.next_line().await {
-                let line = line.trim();
-                if line.is_empty() {
-                    continue;
-                }
-                if warn {
-                    warn!("{line}");
-                } else {
-                    info!("{line}");
-                }
-            }
-        }
-        .instrument(span),
+fn init_tracing(verbose: u8) {
+    let filter = match verbose {
+        0 => Level::INFO,
+        1 => Level::DEBUG,
+        2..=u8::MAX => Level::TRACE,
+    };
+
+    let indicatif_layer = IndicatifLayer::new().with_max_progress_bars(
+        // 3 searchers, 5 downloaders + 5 for each ytdlp subspan, 2 taggers by default, + 2 progress bars
+        17,
+        Some(
+            ProgressStyle::with_template("...and {pending_progress_bars} more not shown above.")
+                .expect("valid template"),
+        ),
     );
+
+    tracing_subscriber::registry()
+        .with(
+            fmt::layer()
+                .without_time()
+                .compact()
+                .with_writer(indicatif_layer.get_stderr_writer()),
+        )
+        .with(Targets::new().with_target("sptfydl", filter))
+        .with(indicatif_layer)
+        .init();
 }
 
 fn handle_exit() {
@@ -536,33 +508,3 @@ fn handle_exit() {
     }
     exit(1);
 }
-
-const SPOTIFY_CONFIG_NAME: &str = "spotify_oauth.yaml";
-
-#[derive(Serialize, Deserialize)]
-struct SpotifyOauth {
-    client_id: String,
-    client_secret: String,
-}
-
-#[inline]
-fn get_spotify_oauth() -> anyhow::Result<SpotifyOauth> {
-    if let Ok(oauth) = load(SPOTIFY_CONFIG_NAME) {
-        Ok(oauth)
-    } else {
-        let client_id = Input::new()
-            .with_prompt("spotify client_id?")
-            .interact_text()?;
-        let client_secret = Password::new()
-            .with_prompt("spotify client_secret?")
-            .interact()?;
-
-        let oauth = SpotifyOauth {
-            client_id,
-            client_secret,
-        };
-        save(&oauth, SPOTIFY_CONFIG_NAME)?;
-
-        Ok(oauth)
-    }
-}
@@ -0,0 +1,324 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::{
+    sync::{Mutex as AsyncMutex, OnceCell},
+    time::{Instant, sleep_until},
+};
+use tracing::{debug, instrument};
+
+use crate::{CLIENT, spotify::Metadata};
+
+/// The public [MusicBrainz](https://musicbrainz.org) api.
+pub const DEFAULT_URL: &str = "https://musicbrainz.org/ws/2";
+
+/// The api allows one request per second.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The api asks for a user agent that identifies the application.
+const USER_AGENT: &str = concat!(
+    "sptfydl/",
+    env!("CARGO_PKG_VERSION"),
+    " (https://github.com/Trevrosa/sptfydl)"
+);
+
+/// The musicbrainz identifiers (MBIDs) of a track.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MusicBrainzIds {
+    pub recording: Option<String>,
+    pub artists: Vec<String>,
+    pub release: Option<String>,
+    pub release_group: Option<String>,
+    pub album_artists: Vec<String>,
+}
+
+/// A release found by barcode, looked up at most once, shared by everything that waits for it.
+type ReleaseCell = Arc<OnceCell<Option<Release>>>;
+
+/// Finds musicbrainz ids with a musicbrainz-compatible api, by ISRC and UPC.
+///
+/// Clones share the rate limit and the releases found by barcode.
+#[derive(Debug, Clone)]
+pub struct MusicBrainz {
+    base_url: String,
+    /// when the next request can be sent
+    next_request: Arc<AsyncMutex<Instant>>,
+    releases: Arc<Mutex<HashMap<String, ReleaseCell>>>,
+}
+
+impl MusicBrainz {
+    /// Use the musicbrainz api at `base_url`, like [`DEFAULT_URL`].
+    #[must_use]
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let mut base_url = base_url.into();
+        while base_url.ends_with('/') {
+            base_url.pop();
+        }
+        Self {
+            base_url,
+            next_request: Arc::new(AsyncMutex::new(Instant::now())),
+            releases: Arc::default(),
+        }
+    }
+
+    async fn get<T: for<'a> Deserialize<'a>>(
+        &self,
+        endpoint: &str,
+        query: &[(&str, &str)],
+    ) -> anyhow::Result<Option<T>> {
+        {
+            let mut next_request = self.next_request.lock().await;
+            sleep_until(*next_request).await;
+            *next_request = Instant::now() + REQUEST_INTERVAL;
+        }
+
+        let resp = CLIENT
+            .get(format!("{}{endpoint}", self.base_url))
+            .header("User-Agent", USER_AGENT)
+            .header("Accept", "application/json")
+            .query(query)
+            .query(&[("fmt", "json")])
+            .send()
+            .await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            return Err(anyhow!("got {}: {:?}", resp.status(), resp.text().await));
+        }
+
+        Ok(Some(resp.json().await?))
+    }
+
+    /// Find the ids of the track described by `metadata`, by its ISRC and its album's UPC.
+    ///
+    /// Returns `None` if neither could be found.
+    ///
+    /// # Errors
+    ///
+    /// A request failed, or its response could not be parsed.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal `Mutex` poisons.
+    #[instrument(name = "musicbrainz", skip_all, fields(track = metadata.name))]
+    pub async fn lookup(&self, metadata: &Metadata) -> anyhow::Result<Option<MusicBrainzIds>> {
+        let release = match metadata.upc.as_deref() {
+            Some(upc) => {
+                let cell = self
+                    .releases
+                    .lock()
+                    .expect("should not be poisoned")
+                    .entry(upc.to_string())
+                    .or_default()
+                    .clone();
+                cell.get_or_try_init(|| self.release(upc)).await?.clone()
+            }
+            None => None,
+        };
+
+        let recordings = match metadata.external_ids.isrc.as_deref() {
+            Some(isrc) => self
+                .get::<IsrcLookup>(
+                    &format!("/isrc/{isrc}"),
+                    &[("inc", "artist-credits+releases+release-groups")],
+                )
+                .await?
+                .map(|lookup| lookup.recordings)
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        debug!("found {} recordings", recordings.len());
+
+        Ok(choose(recordings, release, metadata))
+    }
+
+    /// Find the release with the barcode `upc`.
+    async fn release(&self, upc: &str) -> anyhow::Result<Option<Release>> {
+        let query = format!("barcode:{upc}");
+        let releases = self
+            .get::<ReleaseSearch>("/release", &[("query", &query)])
+            .await?
+            .map(|search| search.releases)
+            .unwrap_or_default();
+
+        let release = releases.into_iter().find(|release| {
+            release
+                .barcode
+                .as_deref()
+                .is_some_and(|b| same_barcode(b, upc))
+        });
+        debug!(
+            "found release {:?} for {upc}",
+            release.as_ref().map(|r| &r.id)
+        );
+        Ok(release)
+    }
+}
+
+/// Barcodes can be written as a UPC-A, or as an EAN-13 with a leading zero.
+fn same_barcode(a: &str, b: &str) -> bool {
+    a.trim_start_matches('0') == b.trim_start_matches('0')
+}
+
+/// <https://musicbrainz.org/doc/MusicBrainz_API#isrc>
+#[derive(Deserialize, Debug)]
+struct IsrcLookup {
+    #[serde(default)]
+    recordings: Vec<Recording>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReleaseSearch {
+    #[serde(default)]
+    releases: Vec<Release>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Recording {
+    id: String,
+    /// in milliseconds
+    length: Option<u32>,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    releases: Vec<Release>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Release {
+    id: String,
+    #[serde(default)]
+    title: String,
+    barcode: Option<String>,
+    #[serde(rename = "release-group")]
+    group: Option<ReleaseGroup>,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ReleaseGroup {
+    id: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ArtistCredit {
+    artist: Artist,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Artist {
+    id: String,
+}
+
+fn artist_ids(credits: &[ArtistCredit]) -> Vec<String> {
+    credits.iter().map(|c| c.artist.id.clone()).collect()
+}
+
+/// Pick the recording of `metadata` from those sharing its ISRC, and combine it with its album's `release`.
+///
+/// Prefers the recording on `release`, then the one closest in length.
+/// Without a `release`, falls back to a release of the recording with the same title as the album.
+fn choose(
+    recordings: Vec<Recording>,
+    release: Option<Release>,
+    metadata: &Metadata,
+) -> Option<MusicBrainzIds> {
+    let on_release = |recording: &Recording| {
+        release
+            .as_ref()
+            .is_some_and(|release| recording.releases.iter().any(|r| r.id == release.id))
+    };
+    let length_difference = |recording: &Recording| {
+        recording
+            .length
+            .map_or(u32::MAX, |length| length.abs_diff(metadata.duration_ms))
+    };
+    let recording = recordings
+        .into_iter()
+        .min_by_key(|r| (!on_release(r), length_difference(r)));
+
+    let release = release.or_else(|| {
+        let album = metadata.album_name.as_deref()?;
+        recording
+            .as_ref()?
+            .releases
+            .iter()
+            .find(|r| r.title.eq_ignore_ascii_case(album))
+            .cloned()
+    });
+
+    if recording.is_none() && release.is_none() {
+        return None;
+    }
+
+    let mut ids = MusicBrainzIds::default();
+    if let Some(recording) = recording {
+        ids.artists = artist_ids(&recording.artist_credit);
+        ids.recording = Some(recording.id);
+    }
+    if let Some(release) = release {
+        ids.album_artists = artist_ids(&release.artist_credit);
+        ids.release_group = release.group.map(|group| group.id);
+        ids.release = Some(release.id);
+    }
+    Some(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spotify::Metadata;
+
+    use super::{IsrcLookup, MusicBrainzIds, ReleaseSearch, choose};
+
+    #[test]
+    fn choose_recording() {
+        let lookup: IsrcLookup = serde_json::from_str(
+            r#"{"recordings": [
+                {"id": "r1", "length": 200000, "artist-credit": [{"artist": {"id": "a1"}}],
+                 "releases": [{"id": "single", "title": "Song"}]},
+                {"id": "r2", "length": 180000, "artist-credit": [{"artist": {"id": "a1"}}, {"artist": {"id": "a2"}}],
+                 "releases": [{"id": "album", "title": "Album"}]}
+            ]}"#,
+        )
+        .unwrap();
+        let search: ReleaseSearch = serde_json::from_str(
+            r#"{"releases": [{"id": "album", "title": "Album", "barcode": "0123456789012",
+                "release-group": {"id": "g1"}, "artist-credit": [{"artist": {"id": "a1"}}]}]}"#,
+        )
+        .unwrap();
+        let metadata = Metadata {
+            duration_ms: 200_000,
+            album_name: Some("Album".to_string()),
+            ..Default::default()
+        };
+
+        // on the album, even though the single is closer in length
+        let release = search.releases.into_iter().next();
+        assert_eq!(
+            choose(lookup.recordings.clone(), release, &metadata),
+            Some(MusicBrainzIds {
+                recording: Some("r2".to_string()),
+                artists: vec!["a1".to_string(), "a2".to_string()],
+                release: Some("album".to_string()),
+                release_group: Some("g1".to_string()),
+                album_artists: vec!["a1".to_string()],
+            })
+        );
+
+        // closest in length, without a release of the same name
+        let ids = choose(lookup.recordings, None, &metadata).unwrap();
+        assert_eq!(ids.recording.as_deref(), Some("r1"));
+        assert_eq!(ids.release, None);
+
+        assert_eq!(choose(Vec::new(), None, &metadata), None);
+    }
+}
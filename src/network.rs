@@ -0,0 +1,107 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use anyhow::Context;
+use reqwest::{Client, Proxy};
+use tracing::warn;
+
+/// The user agent ytmusic is sent by default.
+const DEFAULT_USER_AGENT: &str =
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:88.0) Gecko/20100101 Firefox/88.0";
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_mins(1);
+
+static CONFIGURED: OnceLock<(Client, Network)> = OnceLock::new();
+
+/// Which IP version to connect with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpFamily {
+    V4,
+    V6,
+}
+
+/// How to connect to spotify, ytmusic and the other apis.
+#[derive(Debug, Clone)]
+pub struct Network {
+    /// An http, https or socks5 proxy url.
+    pub proxy: Option<String>,
+    pub connect_timeout: Duration,
+    /// How long to wait for each read of a response.
+    pub read_timeout: Duration,
+    /// Replaces the user agent sent to spotify, ytmusic and cover hosts.
+    pub user_agent: Option<String>,
+    /// Connect with only this IP version, instead of either.
+    pub ip_family: Option<IpFamily>,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self {
+            proxy: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            user_agent: None,
+            ip_family: None,
+        }
+    }
+}
+
+impl Network {
+    fn client(&self) -> anyhow::Result<Client> {
+        let mut builder = Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .read_timeout(self.read_timeout);
+
+        if let Some(proxy) = &self.proxy {
+            let proxy = Proxy::all(proxy).with_context(|| format!("invalid proxy {proxy}"))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        builder = match self.ip_family {
+            Some(IpFamily::V4) => builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            Some(IpFamily::V6) => builder.local_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+            None => builder,
+        };
+
+        Ok(builder.build()?)
+    }
+}
+
+/// Use `network` for every request sent from now on, through [`CLIENT`](crate::CLIENT).
+///
+/// Only the first configuration is used, and it must be set before [`CLIENT`](crate::CLIENT) is first used.
+///
+/// # Errors
+///
+/// The proxy url is invalid, or the client could not be built.
+pub fn configure(network: Network) -> anyhow::Result<()> {
+    let client = network.client()?;
+    if CONFIGURED.set((client, network)).is_err() {
+        warn!("network was already configured");
+    }
+    Ok(())
+}
+
+/// The client for [`CLIENT`](crate::CLIENT): the configured one, or else one with the default [`Network`].
+pub(crate) fn client() -> Client {
+    match CONFIGURED.get() {
+        Some((client, _)) => client.clone(),
+        None => Network::default()
+            .client()
+            .expect("the default network is valid"),
+    }
+}
+
+/// The user agent to send to ytmusic, which refuses some clients.
+pub(crate) fn user_agent() -> &'static str {
+    CONFIGURED
+        .get()
+        .and_then(|(_, network)| network.user_agent.as_deref())
+        .unwrap_or(DEFAULT_USER_AGENT)
+}
@@ -0,0 +1,225 @@
+use std::{
+    fmt::Write as FmtWrite,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{IterExt, spotify::Metadata};
+
+/// A successfully downloaded track, as it should appear in a playlist file.
+#[derive(Debug, Clone)]
+pub struct PlaylistEntry {
+    pub path: PathBuf,
+    pub spotify_id: String,
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: Option<String>,
+    pub duration_ms: u32,
+}
+
+impl PlaylistEntry {
+    #[must_use]
+    pub fn new(path: impl Into<PathBuf>, metadata: &Metadata) -> Self {
+        Self {
+            path: path.into(),
+            spotify_id: metadata.spotify_id.clone(),
+            title: metadata.name.clone(),
+            artists: metadata.artists.iter().map(|a| a.name.clone()).collect(),
+            album: metadata.album_name.clone(),
+            duration_ms: metadata.duration_ms,
+        }
+    }
+
+    /// `artists - title`, or just `title` if there are no artists.
+    fn display_title(&self) -> String {
+        if self.artists.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} - {}", self.artists.iter().join(", "), self.title)
+        }
+    }
+}
+
+/// Write an extended m3u playlist of `entries` to `path`, with paths relative to the parent of `path`.
+///
+/// `entries` are written in the order given.
+///
+/// # Errors
+///
+/// See [`fs::write`] and [`std::path::absolute`].
+pub fn write_m3u8(path: &Path, entries: &[PlaylistEntry]) -> io::Result<()> {
+    let dir = playlist_dir(path)?;
+    fs::write(path, m3u8(&dir, entries)?)
+}
+
+/// Write an xspf playlist named `title` of `entries` to `path`, with locations relative to the parent of `path`.
+///
+/// `entries` are written in the order given.
+///
+/// # Errors
+///
+/// See [`fs::write`] and [`std::path::absolute`].
+pub fn write_xspf(path: &Path, title: &str, entries: &[PlaylistEntry]) -> io::Result<()> {
+    let dir = playlist_dir(path)?;
+    fs::write(path, xspf(&dir, title, entries)?)
+}
+
+fn playlist_dir(playlist: &Path) -> io::Result<PathBuf> {
+    let playlist = std::path::absolute(playlist)?;
+    Ok(playlist
+        .parent()
+        .map_or_else(|| PathBuf::from("/"), Path::to_path_buf))
+}
+
+fn m3u8(dir: &Path, entries: &[PlaylistEntry]) -> io::Result<String> {
+    let mut out = String::with_capacity(entries.len() * 100);
+    out.push_str("#EXTM3U\n");
+
+    for entry in entries {
+        let location = relative_location(dir, &entry.path)?;
+        let secs = entry.duration_ms.div_ceil(1000);
+        // titles can't span lines
+        let title = entry.display_title().replace(['\r', '\n'], " ");
+        let _ = writeln!(out, "#EXTINF:{secs},{title}");
+        out.push_str(&location);
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+// https://www.xspf.org/spec
+fn xspf(dir: &Path, title: &str, entries: &[PlaylistEntry]) -> io::Result<String> {
+    let mut out = String::with_capacity(entries.len() * 250);
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n");
+    let _ = writeln!(out, "  <title>{}</title>", xml_escape(title));
+    out.push_str("  <trackList>\n");
+
+    for entry in entries {
+        let location = uri_escape(&relative_location(dir, &entry.path)?);
+        out.push_str("    <track>\n");
+        let _ = writeln!(out, "      <location>{}</location>", xml_escape(&location));
+        let _ = writeln!(out, "      <title>{}</title>", xml_escape(&entry.title));
+        if !entry.artists.is_empty() {
+            let creator = entry.artists.iter().join(", ");
+            let _ = writeln!(out, "      <creator>{}</creator>", xml_escape(&creator));
+        }
+        if let Some(album) = entry.album.as_deref() {
+            let _ = writeln!(out, "      <album>{}</album>", xml_escape(album));
+        }
+        let _ = writeln!(out, "      <duration>{}</duration>", entry.duration_ms);
+        out.push_str("    </track>\n");
+    }
+
+    out.push_str("  </trackList>\n");
+    out.push_str("</playlist>\n");
+
+    Ok(out)
+}
+
+/// `file` relative to `dir`, always `/`-separated so the playlist works across platforms.
+fn relative_location(dir: &Path, file: &Path) -> io::Result<String> {
+    let file = std::path::absolute(file)?;
+    let relative = relative_path(dir, &file);
+    Ok(relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .join("/"))
+}
+
+/// Find the path to `to` from the directory `from`. Both paths should be absolute.
+///
+/// Falls back to `to` if the paths share no root (like different drives on windows).
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return to.iter().collect();
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    relative.extend(&to[common..]);
+
+    relative
+}
+
+fn xml_escape(str: &str) -> String {
+    let mut escaped = String::with_capacity(str.len());
+    for c in str.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Percent-encode everything but unreserved characters and `/`.
+fn uri_escape(str: &str) -> String {
+    let mut escaped = String::with_capacity(str.len());
+    for byte in str.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            escaped.push(byte as char);
+        } else {
+            let _ = write!(escaped, "%{byte:02X}");
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{PlaylistEntry, m3u8, relative_path};
+
+    #[test]
+    fn relative_paths() {
+        let dir = Path::new("/music/playlist");
+
+        assert_eq!(
+            relative_path(dir, Path::new("/music/playlist/01 - a.mp3")),
+            PathBuf::from("01 - a.mp3")
+        );
+        assert_eq!(
+            relative_path(dir, Path::new("/music/artist/album/01 - a.mp3")),
+            PathBuf::from("../artist/album/01 - a.mp3")
+        );
+        assert_eq!(
+            relative_path(dir, Path::new("/music/playlist/sub/a.mp3")),
+            PathBuf::from("sub/a.mp3")
+        );
+    }
+
+    #[test]
+    fn m3u8_entries() {
+        let entry = |path: &str, title: &str, duration_ms| PlaylistEntry {
+            path: PathBuf::from(path),
+            spotify_id: String::new(),
+            title: title.to_string(),
+            artists: vec!["x".to_string(), "y".to_string()],
+            album: None,
+            duration_ms,
+        };
+        let entries = [
+            entry("/music/list/b.mp3", "b", 200_001),
+            entry("/music/x/a.mp3", "a", 1000),
+        ];
+
+        let out = m3u8(Path::new("/music/list"), &entries).unwrap();
+        assert_eq!(
+            out,
+            "#EXTM3U\n#EXTINF:201,x, y - b\nb.mp3\n#EXTINF:1,x, y - a\n../x/a.mp3\n"
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    num::NonZeroU32,
+    path::Path,
+    sync::OnceLock,
+    time::SystemTime,
+};
+
+use anyhow::{Context, anyhow};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use ring::{
+    aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::save_dir;
+
+/// The first line of encrypted files.
+const HEADER: &str = "sptfydl-encrypted-v1\n";
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+/// PBKDF2-HMAC-SHA256 iterations, as recommended by OWASP.
+const ITERATIONS: NonZeroU32 = NonZeroU32::new(600_000).expect("not zero");
+
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+
+/// Encrypt secret files saved from now on with `passphrase`, and use it to decrypt them.
+///
+/// Only the first passphrase set is used.
+pub fn set_passphrase(passphrase: String) {
+    if PASSPHRASE.set(passphrase).is_err() {
+        warn!("passphrase was already set");
+    }
+}
+
+/// Save `obj` to the secret file `name` in the config dir. See [`save_str`].
+///
+/// # Errors
+///
+/// - See [`save_str`].
+/// - [`serde_yaml`] serialization failed.
+pub fn save<T: Serialize>(obj: &T, name: &str) -> anyhow::Result<()> {
+    save_str(&serde_yaml::to_string(obj)?, name)
+}
+
+/// Load the secret file `name` from the config dir as `T`. See [`load_str`].
+///
+/// # Errors
+///
+/// - See [`load_str`].
+/// - [`serde_yaml`] deserialization failed.
+pub fn load<T: for<'a> Deserialize<'a>>(name: &str) -> anyhow::Result<Option<T>> {
+    let Some(contents) = load_str(name)? else {
+        return Ok(None);
+    };
+    Ok(Some(serde_yaml::from_str(&contents)?))
+}
+
+/// Save `contents` to the secret file `name` in the config dir, only readable by the current user,
+/// and encrypted if a passphrase was set with [`set_passphrase`].
+///
+/// # Errors
+///
+/// The file could not be written, or encryption failed.
+pub fn save_str(contents: &str, name: &str) -> anyhow::Result<()> {
+    let contents = match PASSPHRASE.get() {
+        Some(passphrase) => encrypt(passphrase, contents)?,
+        None => contents.to_string(),
+    };
+    write_private(&save_dir().join(name), &contents)?;
+    Ok(())
+}
+
+/// Load the secret file `name` from the config dir, decrypting it if needed.
+///
+/// Returns `None` if it doesn't exist. Files saved without a passphrase are encrypted once one is set.
+///
+/// # Errors
+///
+/// - The file could not be read.
+/// - The file is encrypted, and no passphrase was set, or it is wrong.
+pub fn load_str(name: &str) -> anyhow::Result<Option<String>> {
+    let path = save_dir().join(name);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("reading {name}")),
+    };
+
+    let passphrase = PASSPHRASE.get();
+    if let Some(encrypted) = contents.strip_prefix(HEADER) {
+        let passphrase = passphrase.ok_or_else(|| {
+            anyhow!("{name} is encrypted: set SPTFYDL_PASSPHRASE or --passphrase-file")
+        })?;
+        return decrypt(passphrase, encrypted)
+            .with_context(|| format!("decrypting {name}"))
+            .map(Some);
+    }
+
+    if passphrase.is_some() {
+        match save_str(&contents, name) {
+            Ok(()) => debug!("encrypted {name}"),
+            Err(err) => warn!("failed to encrypt {name}: {err}"),
+        }
+    } else if let Err(err) = restrict_permissions(&path) {
+        warn!("failed to restrict the permissions of {name}: {err}");
+    }
+    Ok(Some(contents))
+}
+
+/// When the secret file `name` was last saved, if it exists.
+#[must_use]
+pub fn saved_at(name: &str) -> Option<SystemTime> {
+    fs::metadata(save_dir().join(name)).ok()?.modified().ok()
+}
+
+/// The names of the files in the config dir that start with `prefix`.
+///
+/// # Errors
+///
+/// The config dir could not be read.
+pub fn find(prefix: &str) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(save_dir())? {
+        if let Some(name) = entry?.file_name().to_str()
+            && name.starts_with(prefix)
+        {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// Remove the secret file `name` from the config dir. Returns whether it existed.
+///
+/// # Errors
+///
+/// The file exists, but could not be removed.
+pub fn remove(name: &str) -> io::Result<bool> {
+    match fs::remove_file(save_dir().join(name)) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Write `contents` to `path`, with `0600` permissions on unix.
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    let mut options = File::options();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    // the mode only applies to new files
+    restrict_permissions(path)?;
+    file.write_all(contents.as_bytes())
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+fn restrict_permissions(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> LessSafeKey {
+    let mut key = [0; KEY_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        ITERATIONS,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key).expect("key has the right length");
+    LessSafeKey::new(key)
+}
+
+/// Encrypt `plain` with ChaCha20-Poly1305, using a key derived from `passphrase` with a random salt.
+///
+/// The result is [`HEADER`], then the salt, nonce and ciphertext in base64.
+fn encrypt(passphrase: &str, plain: &str) -> anyhow::Result<String> {
+    let rng = SystemRandom::new();
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|()| rng.fill(&mut nonce))
+        .map_err(|_| anyhow!("failed to generate random bytes"))?;
+
+    let mut sealed = plain.as_bytes().to_vec();
+    derive_key(passphrase, &salt)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut sealed,
+        )
+        .map_err(|_| anyhow!("failed to encrypt"))?;
+
+    let mut data = Vec::with_capacity(SALT_LEN + NONCE_LEN + sealed.len());
+    data.extend(salt);
+    data.extend(nonce);
+    data.extend(sealed);
+    Ok(format!("{HEADER}{}\n", BASE64_STANDARD.encode(data)))
+}
+
+/// Decrypt what [`encrypt`] made, without its [`HEADER`].
+fn decrypt(passphrase: &str, encrypted: &str) -> anyhow::Result<String> {
+    let mut data = BASE64_STANDARD.decode(encrypted.trim())?;
+    if data.len() < SALT_LEN + NONCE_LEN {
+        return Err(anyhow!("too short"));
+    }
+    let (salt, rest) = data.split_at_mut(SALT_LEN);
+    let (nonce, sealed) = rest.split_at_mut(NONCE_LEN);
+
+    let nonce = Nonce::try_assume_unique_for_key(nonce).expect("nonce has the right length");
+    let plain = derive_key(passphrase, salt)
+        .open_in_place(nonce, Aad::empty(), sealed)
+        .map_err(|_| anyhow!("wrong passphrase, or the file is corrupted"))?;
+
+    Ok(String::from_utf8(plain.to_vec())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HEADER, decrypt, encrypt};
+
+    #[test]
+    fn roundtrip() {
+        let encrypted = encrypt("hunter2", "client_secret: abc").unwrap();
+        let encrypted = encrypted.strip_prefix(HEADER).unwrap();
+
+        assert_eq!(decrypt("hunter2", encrypted).unwrap(), "client_secret: abc");
+        assert!(decrypt("hunter3", encrypted).is_err());
+        assert!(decrypt("hunter2", "c2hvcnQ=").is_err());
+    }
+}
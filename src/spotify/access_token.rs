@@ -77,6 +77,12 @@ impl AccessToken {
         Some(resp)
     }
 
+    /// When the token expires, if it's known when it was granted.
+    #[must_use]
+    pub fn expires_at(&self) -> Option<chrono::DateTime<Utc>> {
+        Some(self.granted? + TimeDelta::seconds(self.expires_in.cast_signed()))
+    }
+
     #[must_use]
     pub fn expired(&self) -> bool {
         self.granted
@@ -1,147 +1,307 @@
 pub mod access_token;
 pub use access_token::AccessToken;
 
+pub mod tokens;
+pub use tokens::Tokens;
+
 pub mod search;
 pub use search::get_from_url;
 
 pub mod types;
-pub use types::{Extraction, Metadata, Track};
+pub use types::{Extraction, MatchStrategy, Metadata, ReleaseDate, SearchMatch, Track};
 
 use std::{
+    collections::{HashMap, HashSet},
+    env,
     fmt::Write as FmtWrite,
     fs,
     io::{Write, stdin, stdout},
+    path::Path,
     sync::Arc,
     time::{Duration, Instant},
 };
 
-use anyhow::anyhow;
+use anyhow::{Context, anyhow};
 use dialoguer::Select;
 use indicatif::ProgressStyle;
-use tokio::{sync::mpsc, time::sleep};
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
 use tracing::{Instrument, Span, debug, info, info_span, trace, warn};
 use tracing_indicatif::span_ext::IndicatifSpanExt;
 
 use crate::{
-    IterExt, load, load_str, save, save_str,
-    spotify::search::{SimplifiedArtist, SpotifyTrack, bulk_artists, bulk_many_artists},
+    IterExt, secrets,
+    spotify::search::{
+        SimplifiedArtist, SpotifyArtist, SpotifyTrack, bulk_albums, bulk_artists,
+        bulk_many_artists, bulk_tracks,
+    },
     ytmusic::{
         SearchResult as YtSearchResult,
-        auth::{Browser, parse_cookie},
+        auth::{Browser, import_cookie},
         search::SearchFilter,
     },
 };
 
 use super::ytmusic;
 
-const SPOTIFY_TOKEN_CONFIG_NAME: &str = "spotify_token.yaml";
-const YTM_DATA_CONFIG_NAME: &str = "ytm_browser_data";
+/// The spotify account used when none is chosen.
+pub const DEFAULT_ACCOUNT: &str = "default";
+/// The secret file the ytmusic cookie is saved in.
+pub const YTM_DATA_CONFIG_NAME: &str = "ytm_browser_data";
 
-/// Returns `Vec<(usize, String)>` because some tracks may not be found from ytmusic,
-/// so some tracks may be missing,
-/// so we return the track number as well
+/// Search ytmusic for the tracks of `spotify_url`, collecting the tracks [`stream_spotify`] sends
+/// into an [`Extraction`] once every search has finished.
+///
+/// Some tracks may not be found on ytmusic, so each track keeps its track number.
 ///
 /// # Errors
 ///
 /// This function fails if:
-/// - We could not get a new [`AccessToken`], and one is not cached.
-/// - Cookies were required to be prompted and `no_interaction` was true.
+/// - [`stream_spotify`] failed.
 /// - We got no urls from ytmusic.
-///
-/// # Panics
-///
-/// This function panics if we could not get the cookies from `stdin`.
 pub async fn extract_spotify(
-    id: &str,
-    secret: &str,
+    tokens: Tokens,
+    raw_cookie: Option<&str>,
     spotify_url: &str,
-    searchers: usize,
-    no_interaction: bool,
-    retries: usize,
-    isrc: bool,
+    options: SearchOptions,
 ) -> anyhow::Result<Extraction> {
-    let token = load::<AccessToken>(SPOTIFY_TOKEN_CONFIG_NAME);
+    stream_spotify(tokens, raw_cookie, spotify_url, options)
+        .await?
+        .extract()
+        .await
+}
 
-    let token = if let Ok(token) = token {
-        debug!("got spotify token from cache");
-        token
-    } else {
-        request_token_and_save(id, secret).await?
-    };
+/// How to search ytmusic for spotify tracks.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchOptions {
+    /// The number of concurrent searches.
+    pub searchers: usize,
+    /// Always choose the best available result, instead of asking when there is only one track.
+    pub no_interaction: bool,
+    /// The number of times to try each search.
+    pub retries: usize,
+    /// Search by isrc when it's known, instead of by title and artists.
+    pub isrc: bool,
+}
 
-    let token = if token.expired() {
-        request_token_and_save(id, secret).await?
-    } else {
-        token
-    };
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            searchers: 3,
+            no_interaction: false,
+            retries: 3,
+            isrc: false,
+        }
+    }
+}
+
+/// A search running in the background, started by [`stream_spotify`].
+pub struct SearchStream {
+    /// The name of the playlist or album, if the url was one.
+    pub name: Option<String>,
+    /// The number of spotify tracks being searched for.
+    pub total: usize,
+    /// Tracks are sent here as soon as they are found and have their artists.
+    ///
+    /// Closes once every search has ended.
+    pub tracks: async_channel::Receiver<(usize, Track)>,
+    report: JoinHandle<SearchReport>,
+}
+
+impl SearchStream {
+    /// Wait for every search to end.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the search task panicked.
+    pub async fn finish(self) -> SearchReport {
+        self.report.await.expect("search task should not panic")
+    }
 
-    let (mut spotify_tracks, name) = get_from_url(spotify_url, token.as_ref()).await?;
-    let first_name = spotify_tracks[0].name.clone();
+    /// Wait for every search to end, collecting the found tracks in track order.
+    ///
+    /// # Errors
+    ///
+    /// No track was found.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the search task panicked.
+    pub async fn extract(self) -> anyhow::Result<Extraction> {
+        let mut tracks = Vec::with_capacity(self.total);
+        while let Ok(track) = self.tracks.recv().await {
+            tracks.push(track);
+        }
+        tracks.sort_unstable_by_key(|(track_num, _)| *track_num);
+
+        let name = self.name.clone();
+        let report = self.finish().await;
+
+        if tracks.is_empty() {
+            Err(anyhow!("got no urls"))
+        } else {
+            Ok(Extraction {
+                tracks,
+                name,
+                warnings: report.warnings.iter().map(|(n, _)| *n).collect(),
+                failures: report.failed.len(),
+            })
+        }
+    }
+}
+
+/// The tracks that went wrong during a [`SearchStream`].
+#[derive(Debug, Default)]
+pub struct SearchReport {
+    /// Tracks that could be incorrect.
+    pub warnings: Vec<(usize, Track)>,
+    /// Tracks that could not be found.
+    pub failed: Vec<(usize, SpotifyTrack)>,
+}
+
+/// Get the tracks of `spotify_url` and start searching ytmusic for them in the background.
+///
+/// Returns once the spotify tracks are found.
+///
+/// # Errors
+///
+/// This function fails if:
+/// - We could not get any tracks from `spotify_url`.
+/// - See [`stream_tracks`].
+pub async fn stream_spotify(
+    tokens: Tokens,
+    raw_cookie: Option<&str>,
+    spotify_url: &str,
+    options: SearchOptions,
+) -> anyhow::Result<SearchStream> {
+    let (spotify_tracks, name) = get_from_url(spotify_url, &tokens).await?;
+    if spotify_tracks.is_empty() {
+        return Err(anyhow!("got no tracks from {spotify_url}"));
+    }
 
     info!("got {} tracks", spotify_tracks.len(),);
 
-    let raw_cookie = get_cookies(no_interaction)?;
-
-    let cookie = parse_cookie(&raw_cookie).ok_or(anyhow!("failed to parse cookie"))?;
-    let auth = Browser::new(cookie);
-
-    let (tracks, warnings, failed) = if spotify_tracks.len() == 1 {
-        let track = spotify_tracks.pop().expect("len is 1");
-        debug!("metadata: {track:#?}");
-        info!("searching for {}", track.name);
-        search_one(
-            track,
-            auth.as_ref(),
-            token.as_ref(),
-            no_interaction,
-            retries,
-            isrc,
-        )
-        .await
-    } else {
-        search_many(
-            spotify_tracks,
-            Arc::from(auth.into_inner()),
-            token.as_ref(),
-            searchers,
-            retries,
-            isrc,
-        )
-        .await
+    stream_tracks(tokens, raw_cookie, spotify_tracks, name, options)
+}
+
+/// Start searching ytmusic for `spotify_tracks`, of the playlist or album `name`, in the background.
+///
+/// # Errors
+///
+/// This function fails if:
+/// - There are no `spotify_tracks`.
+/// - `raw_cookie` could not be parsed. Without one, ytmusic is searched anonymously.
+///
+/// # Panics
+///
+/// Will not panic: the only track is taken when there is exactly one.
+pub fn stream_tracks(
+    tokens: Tokens,
+    raw_cookie: Option<&str>,
+    mut spotify_tracks: Vec<SpotifyTrack>,
+    name: Option<String>,
+    options: SearchOptions,
+) -> anyhow::Result<SearchStream> {
+    let Some(first_name) = spotify_tracks.first().map(|t| t.name.clone()) else {
+        return Err(anyhow!("got no tracks to search for"));
     };
 
-    if !failed.is_empty() {
-        warn!("{} songs failed, check report", failed.len());
+    let auth = raw_cookie
+        .map(Browser::from_cookie)
+        .transpose()
+        .context("reading the ytm cookie")?;
+    if auth.is_none() {
+        debug!("searching ytmusic without a cookie");
+    }
+    let SearchOptions {
+        searchers,
+        no_interaction,
+        retries,
+        isrc,
+    } = options;
+
+    let total = spotify_tracks.len();
+    // big enough that searchers never wait for downloaders
+    let (tracks_tx, tracks_rx) = async_channel::bounded(total);
+    let report_name = name.clone().unwrap_or(first_name);
+
+    let report = tokio::spawn(async move {
+        let report = if spotify_tracks.len() == 1 {
+            let track = spotify_tracks.pop().expect("len is 1");
+            debug!("metadata: {track:#?}");
+            info!("searching for {}", track.name);
+            search_one(
+                track,
+                auth.as_ref(),
+                &tokens,
+                no_interaction,
+                retries,
+                isrc,
+                tracks_tx,
+            )
+            .await
+        } else {
+            search_many(
+                spotify_tracks,
+                auth.map(Arc::new),
+                &tokens,
+                searchers,
+                retries,
+                isrc,
+                tracks_tx,
+            )
+            .await
+        };
 
-        let mut report = String::with_capacity(failed.len() * 150);
-        for (n, t) in &failed {
-            let _ = writeln!(report, "track #{n}: {t:#?}");
+        if !report.failed.is_empty() {
+            write_failed_report(&report_name, &report.failed);
         }
 
-        let name = name.as_deref().unwrap_or(&first_name);
-        let path = format!("failed-{name}.txt");
+        report
+    });
 
-        let _ = fs::write(path, report);
-    }
+    Ok(SearchStream {
+        name,
+        total,
+        tracks: tracks_rx,
+        report,
+    })
+}
 
-    if tracks.is_empty() {
-        Err(anyhow!("got no urls"))
-    } else {
-        Ok(Extraction {
-            tracks,
-            name,
-            warnings,
-            failures: failed.len(),
-        })
+fn write_failed_report(name: &str, failed: &[(usize, SpotifyTrack)]) {
+    warn!("{} songs failed, check report", failed.len());
+
+    let mut report = String::with_capacity(failed.len() * 150);
+    for (n, t) in failed {
+        let _ = writeln!(report, "track #{n}: {t:#?}");
     }
+
+    let path = format!("failed-{name}.txt");
+
+    let _ = fs::write(path, report);
 }
 
 const RETRY_DELAY: Duration = Duration::from_secs(3);
 
-/// (`urls`, `warns`, `fails`)
-type SearchResult = (Vec<(usize, Track)>, Vec<usize>, Vec<(usize, SpotifyTrack)>);
+/// How long a found track waits for others to share a request for artists with.
+const PROMOTE_INTERVAL: Duration = Duration::from_secs(5);
+/// The number of artists that fit in one request.
+const ARTISTS_PER_REQUEST: usize = 50;
+
+/// A track found on ytmusic that does not have its full artists yet.
+struct Searched {
+    track_num: usize,
+    url: String,
+    track: SpotifyTrack,
+    search_match: SearchMatch,
+    /// the best result was not available
+    warn: bool,
+}
 
-/// search ytmusic for the `spotify_tracks`
+/// search ytmusic for the `spotify_tracks`, sending each one to `output` as soon as possible.
 ///
 /// # Panics
 ///
@@ -149,19 +309,19 @@ type SearchResult = (Vec<(usize, Track)>, Vec<usize>, Vec<(usize, SpotifyTrack)>
 #[inline]
 async fn search_many(
     spotify_tracks: Vec<SpotifyTrack>,
-    yt_auth: Arc<str>,
-    spotify_auth: &str,
+    yt_auth: Option<Arc<Browser>>,
+    spotify_auth: &Tokens,
     searchers: usize,
     retries: usize,
     isrc: bool,
-) -> SearchResult {
+    output: async_channel::Sender<(usize, Track)>,
+) -> SearchReport {
     let start = Instant::now();
     let expected_tracks = spotify_tracks.len();
 
     let (tracks_tx, tracks_rx) = async_channel::bounded(searchers);
 
-    let (results_tx, mut results_rx) = mpsc::channel(expected_tracks);
-    let (warns_tx, mut warns_rx) = mpsc::channel(expected_tracks);
+    let (searched_tx, searched_rx) = mpsc::channel(expected_tracks);
     let (fails_tx, mut failed_rx) = mpsc::channel(expected_tracks);
 
     debug!("channel setup took {:?}", start.elapsed());
@@ -187,9 +347,8 @@ async fn search_many(
 
     let mut searcher_handles = Vec::with_capacity(searchers);
     for task in 0..searchers {
-        let output = results_tx.clone();
+        let searched = searched_tx.clone();
         let tracks = tracks_rx.clone();
-        let warns = warns_tx.clone();
         let failed = fails_tx.clone();
         let yt_auth = yt_auth.clone();
 
@@ -206,17 +365,14 @@ async fn search_many(
                     debug!("metadata: {track:#?}");
                     info!("{:?}", track.name);
 
-                    let query = preferred_query(&track, isrc);
-                    let Some(mut results) = search_retrying(&query, &yt_auth, retries).await else {
-                        failed
-                            .send((i + 1, track))
-                            .await
-                            .expect("shouldnt be closed");
+                    let (query, strategy) = preferred_query(&track, isrc);
+                    let Some(mut results) =
+                        search_retrying(&query, yt_auth.as_deref(), retries).await
+                    else {
+                        failed.send((i, track)).await.expect("shouldnt be closed");
                         continue;
                     };
 
-                    results[0].title.push_str("Best Result");
-
                     debug!("got {} results", results.len());
 
                     let choice = {
@@ -227,12 +383,22 @@ async fn search_many(
 
                     if choice != 0 {
                         warn!("the best result was not available");
-                        warns.send(i).await.expect("shouldnt be closed");
                     }
 
                     let url = results[choice].link_or_default().to_string();
-                    output
-                        .send((i, (url, track)))
+                    let searched_track = Searched {
+                        track_num: i,
+                        url,
+                        track,
+                        search_match: SearchMatch {
+                            strategy,
+                            rank: choice + 1,
+                            title: results.swap_remove(choice).title,
+                        },
+                        warn: choice != 0,
+                    };
+                    searched
+                        .send(searched_track)
                         .await
                         .expect("shouldnt be closed");
                 }
@@ -244,23 +410,13 @@ async fn search_many(
     }
 
     // ensure channels close so `recv_many()` doesn't poll forever
-    drop((results_tx, warns_tx, fails_tx));
+    drop((searched_tx, fails_tx));
 
     debug!("total setup took {:?}", start.elapsed());
 
-    let mut tracks = Vec::with_capacity(expected_tracks);
-    async {
-        while let Some(track) = results_rx.recv().await {
-            Span::current().pb_inc(1);
-            tracks.push(track);
-        }
-    }
-    .instrument(pb_span)
-    .await;
-
-    info!("getting artist data");
-
-    let tracks = promote(tracks, spotify_auth).await;
+    let warnings = promote_incrementally(searched_rx, &output, spotify_auth)
+        .instrument(pb_span)
+        .await;
 
     for handle in searcher_handles {
         if let Err(err) = handle.await {
@@ -268,51 +424,286 @@ async fn search_many(
         }
     }
 
-    debug!("collecting fails / warns from channels");
+    debug!("collecting fails from channel");
 
-    let mut warnings = Vec::new();
-    warns_rx.recv_many(&mut warnings, expected_tracks).await;
     let mut failed = Vec::new();
     failed_rx.recv_many(&mut failed, expected_tracks).await;
 
-    (tracks, warnings, failed)
+    SearchReport { warnings, failed }
 }
 
-/// Converts a tuple (`track_num`, (`url`, `spotify_track`)) into a `Vec<(usize, Track)>` by requesting for full [`search::SpotifyArtist`]s in bulk.
-#[inline]
-async fn promote(
-    urls: Vec<(usize, (String, SpotifyTrack))>,
-    spotify_auth: &str,
+/// Artists and full albums that have already been requested.
+#[derive(Default)]
+struct Known {
+    artists: HashMap<String, SpotifyArtist>,
+    /// full albums as json, by id
+    albums: HashMap<String, serde_json::Value>,
+}
+
+/// Turns [`Searched`] tracks into [`Track`]s as they come in, by requesting for full [`search::SpotifyArtist`]s and albums in bulk.
+///
+/// Tracks are promoted in batches: once a batch has enough new artists to fill a request,
+/// once its first track has waited for [`PROMOTE_INTERVAL`], or once `searched` closes.
+/// Artists and albums are remembered, so each one is only requested once.
+///
+/// Returns the tracks that could be incorrect.
+async fn promote_incrementally(
+    mut searched: mpsc::Receiver<Searched>,
+    output: &async_channel::Sender<(usize, Track)>,
+    spotify_auth: &Tokens,
 ) -> Vec<(usize, Track)> {
-    let artists: Vec<&Vec<SimplifiedArtist>> = urls.iter().map(|u| &u.1.1.artists).collect();
-    let artists = bulk_many_artists(&artists, spotify_auth)
-        .await
-        .expect("failed to get artists");
+    let mut known = Known::default();
+    let mut pending = Vec::new();
+    let mut warnings = Vec::new();
+    let mut deadline = Instant::now();
+
+    loop {
+        let received = if pending.is_empty() {
+            searched.recv().await
+        } else {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if let Ok(received) = timeout(remaining, searched.recv()).await {
+                received
+            } else {
+                trace!("promoting {} tracks after waiting", pending.len());
+                promote(
+                    &mut pending,
+                    &mut known,
+                    output,
+                    &mut warnings,
+                    spotify_auth,
+                )
+                .await;
+                continue;
+            }
+        };
+
+        let Some(track) = received else {
+            break;
+        };
+        Span::current().pb_inc(1);
+
+        if pending.is_empty() {
+            deadline = Instant::now() + PROMOTE_INTERVAL;
+        }
+        pending.push(track);
+
+        if unknown_artists(&pending, &known.artists).len() >= ARTISTS_PER_REQUEST {
+            promote(
+                &mut pending,
+                &mut known,
+                output,
+                &mut warnings,
+                spotify_auth,
+            )
+            .await;
+        }
+    }
+
+    if !pending.is_empty() {
+        promote(
+            &mut pending,
+            &mut known,
+            output,
+            &mut warnings,
+            spotify_auth,
+        )
+        .await;
+    }
+
+    warnings
+}
 
-    assert_eq!(urls.len(), artists.len());
+/// The artists of `pending` that are not in `known`, without duplicates.
+fn unknown_artists(
+    pending: &[Searched],
+    known: &HashMap<String, SpotifyArtist>,
+) -> Vec<SimplifiedArtist> {
+    let mut seen = HashSet::new();
+    pending
+        .iter()
+        .flat_map(|s| &s.track.artists)
+        // `bulk_many_artists` skips these anyway
+        .filter(|a| a.name.is_some())
+        .filter(|a| {
+            a.id.as_ref()
+                .is_some_and(|id| !known.contains_key(id) && seen.insert(id))
+        })
+        .cloned()
+        .collect()
+}
+
+/// The ids of the partial albums of `pending` that are not in `known`, without duplicates.
+fn unknown_albums<'a>(
+    pending: &'a [Searched],
+    known: &HashMap<String, serde_json::Value>,
+) -> Vec<&'a str> {
+    let mut seen = HashSet::new();
+    pending
+        .iter()
+        .filter_map(|s| s.track.partial_album_id())
+        .filter(|id| !known.contains_key(*id) && seen.insert(*id))
+        .collect()
+}
+
+/// Request the unknown artists and albums of `pending`, then send all of `pending` to `output`.
+async fn promote(
+    pending: &mut Vec<Searched>,
+    known: &mut Known,
+    output: &async_channel::Sender<(usize, Track)>,
+    warnings: &mut Vec<(usize, Track)>,
+    spotify_auth: &Tokens,
+) {
+    let unknown = unknown_artists(pending, &known.artists);
+    if !unknown.is_empty() {
+        debug!("getting data for {} artists", unknown.len());
+        match bulk_many_artists(&[&unknown], spotify_auth).await {
+            Ok(mut artists) => {
+                for artist in artists.pop().unwrap_or_default() {
+                    known.artists.insert(artist.id.clone(), artist);
+                }
+            }
+            Err(err) => warn!("failed to get artists, genres will be missing: {err}"),
+        }
+    }
+
+    let unknown = unknown_albums(pending, &known.albums);
+    if !unknown.is_empty() {
+        debug!("getting data for {} albums", unknown.len());
+        match bulk_albums(&unknown, spotify_auth).await {
+            Ok(albums) => {
+                for album in albums {
+                    if let Some(id) = album.get("id").and_then(|id| id.as_str()) {
+                        known.albums.insert(id.to_string(), album);
+                    }
+                }
+            }
+            Err(err) => warn!("failed to get albums, labels will be missing: {err}"),
+        }
+    }
+
+    for mut searched in pending.drain(..) {
+        if let Some(album) = searched
+            .track
+            .partial_album_id()
+            .and_then(|id| known.albums.get(id))
+        {
+            searched.track.album = Some(album.clone());
+        }
+
+        let artists = searched
+            .track
+            .artists
+            .iter()
+            .filter_map(|wanted| {
+                let id = wanted.id.as_ref()?;
+                known
+                    .artists
+                    .get(id)
+                    .cloned()
+                    .or_else(|| SpotifyArtist::from_simplified(wanted))
+            })
+            .collect();
+
+        let mut metadata = searched.track.into_metadata(artists);
+        metadata.search_match = Some(searched.search_match);
+        let track = Track::new(searched.url, metadata);
+        if searched.warn {
+            warnings.push((searched.track_num, track.clone()));
+        }
+
+        if output.send((searched.track_num, track)).await.is_err() {
+            debug!("nothing is waiting for tracks anymore");
+        }
+    }
+}
+
+/// Get the [`Metadata`] of the tracks with `ids`, with bulk requests for the tracks, their full albums and their artists.
+///
+/// Tracks that could not be found are `None`. Order is preserved.
+///
+/// # Errors
+///
+/// The tracks or their artists could not be requested.
+pub async fn tracks_metadata(
+    ids: &[&str],
+    spotify_auth: &Tokens,
+) -> anyhow::Result<Vec<Option<Metadata>>> {
+    let mut tracks = bulk_tracks(ids, spotify_auth).await?;
+    debug!("got {} tracks", tracks.iter().flatten().count());
+
+    let mut seen = HashSet::new();
+    let album_ids: Vec<String> = tracks
+        .iter()
+        .flatten()
+        .filter_map(SpotifyTrack::partial_album_id)
+        .filter(|id| seen.insert(*id))
+        .map(ToString::to_string)
+        .collect();
+    let album_ids: Vec<&str> = album_ids.iter().map(String::as_str).collect();
+    let mut albums = HashMap::new();
+    match bulk_albums(&album_ids, spotify_auth).await {
+        Ok(full) => {
+            for album in full {
+                if let Some(id) = album.get("id").and_then(|id| id.as_str()) {
+                    albums.insert(id.to_string(), album);
+                }
+            }
+        }
+        Err(err) => warn!("failed to get albums, labels will be missing: {err}"),
+    }
 
-    let mut tracks = Vec::with_capacity(urls.len());
-    for ((track_num, (url, track)), artists) in urls.into_iter().zip(artists) {
-        let metadata = track.into_metadata(artists);
-        tracks.push((track_num, Track::new(url, metadata)));
+    for track in tracks.iter_mut().flatten() {
+        if let Some(album) = track.partial_album_id().and_then(|id| albums.get(id)) {
+            track.album = Some(album.clone());
+        }
+        // only missing for local files
+        track.external_ids.get_or_insert_default();
     }
 
-    tracks
+    let artists: Vec<&Vec<SimplifiedArtist>> =
+        tracks.iter().flatten().map(|t| &t.artists).collect();
+    let mut artists = bulk_many_artists(&artists, spotify_auth).await?.into_iter();
+
+    Ok(tracks
+        .into_iter()
+        .map(|track| {
+            let track = track?;
+            Some(track.into_metadata(artists.next().unwrap_or_default()))
+        })
+        .collect())
 }
 
 #[inline]
 async fn search_one(
-    track: SpotifyTrack,
-    yt_auth: &str,
-    spotify_auth: &str,
+    mut track: SpotifyTrack,
+    yt_auth: Option<&Browser>,
+    spotify_auth: &Tokens,
     no_interaction: bool,
     retries: usize,
     isrc: bool,
-) -> SearchResult {
-    let artists = bulk_artists(&track.artists, spotify_auth).await.unwrap();
-    let query = preferred_query(&track, isrc);
+    output: async_channel::Sender<(usize, Track)>,
+) -> SearchReport {
+    let artists = bulk_artists(&track.artists, spotify_auth)
+        .await
+        .inspect_err(|err| warn!("failed to get artists, genres will be missing: {err}"))
+        .unwrap_or_else(|_| {
+            track
+                .artists
+                .iter()
+                .filter_map(SpotifyArtist::from_simplified)
+                .collect()
+        });
+    if let Some(id) = track.partial_album_id().map(ToString::to_string) {
+        match bulk_albums(&[&id], spotify_auth).await {
+            Ok(mut albums) => track.album = albums.pop().or(track.album),
+            Err(err) => warn!("failed to get the full album: {err}"),
+        }
+    }
+    let (query, strategy) = preferred_query(&track, isrc);
 
     if let Some(mut results) = search_retrying(&query, yt_auth, retries).await {
+        let best_title = results[0].title.clone();
         results[0].title.push_str("Best Result");
 
         debug!("got {} results", results.len());
@@ -331,38 +722,77 @@ async fn search_one(
         }
         .unwrap_or(0);
 
+        let url = results[choice].link_or_default().to_string();
+        let mut metadata = track.into_metadata(artists);
+        metadata.search_match = Some(SearchMatch {
+            strategy,
+            rank: choice + 1,
+            title: if choice == 0 {
+                best_title
+            } else {
+                results.swap_remove(choice).title
+            },
+        });
+        let track = Track::new(url, metadata);
+
         let mut warnings = Vec::with_capacity(1);
         if choice != 0 {
             warn!("the best result was not available");
-            warnings.push(0);
+            warnings.push((0, track.clone()));
         }
 
-        let url = results[choice].link_or_default().to_string();
-        let metadata = track.into_metadata(artists);
+        if output.send((0, track)).await.is_err() {
+            debug!("nothing is waiting for tracks anymore");
+        }
 
-        (vec![(0, Track::new(url, metadata))], warnings, vec![])
+        SearchReport {
+            warnings,
+            failed: vec![],
+        }
     } else {
-        (vec![], vec![], vec![(0, track)])
+        SearchReport {
+            warnings: vec![],
+            failed: vec![(0, track)],
+        }
     }
 }
 
 #[inline]
-fn preferred_query(track: &SpotifyTrack, isrc: bool) -> String {
+fn preferred_query(track: &SpotifyTrack, isrc: bool) -> (String, MatchStrategy) {
     if isrc && let Some(isrc) = track.external_ids.as_ref().and_then(|ids| ids.isrc.clone()) {
-        isrc
+        (isrc, MatchStrategy::Isrc)
     } else {
         let artists = track
             .artists
             .iter()
             .filter_map(|a| a.name.as_ref())
             .join(" ");
-        format!("{} {artists}", track.name,)
+        (format!("{} {artists}", track.name), MatchStrategy::Query)
     }
 }
 
-/// Search `query` with `auth`, retrying `retries` times. Returns `None` if no results could be found after `retries` retries.
+/// Search ytmusic for `track` with `auth` (or anonymously), like [`stream_spotify`] does.
+///
+/// Returns how it was searched, and the results, best first.
+/// Returns `None` if no results could be found after `options.retries` tries.
+pub async fn search_candidates(
+    track: &SpotifyTrack,
+    auth: Option<&Browser>,
+    options: SearchOptions,
+) -> Option<(MatchStrategy, Vec<YtSearchResult>)> {
+    let (query, strategy) = preferred_query(track, options.isrc);
+    debug!("searching for {query:?}");
+    let results = search_retrying(&query, auth, options.retries).await?;
+    Some((strategy, results))
+}
+
+/// Search `query` with `auth` (or anonymously), retrying `retries` times. Returns `None` if no results could be found after `retries` retries.
 #[inline]
-async fn search_retrying(query: &str, auth: &str, retries: usize) -> Option<Vec<YtSearchResult>> {
+async fn search_retrying(
+    query: &str,
+    auth: Option<&Browser>,
+    retries: usize,
+) -> Option<Vec<YtSearchResult>> {
     for attempt in 0..retries {
         if attempt > 0 {
             sleep(RETRY_DELAY).await;
@@ -420,38 +850,153 @@ async fn search_retrying(query: &str, auth: &str, retries: usize) -> Option<Vec<
     None
 }
 
-#[inline]
-fn get_cookies(no_interaction: bool) -> anyhow::Result<String> {
-    if let Ok(cookie) = load_str(YTM_DATA_CONFIG_NAME) {
-        Ok(cookie)
+/// Get the ytmusic cookie from `SPTFYDL_YTM_COOKIE`, `cookie_file`, or the saved one.
+///
+/// Returns `None` if there is none: ytmusic can be searched without one,
+/// a cookie is only needed for region or age restricted results.
+///
+/// # Errors
+///
+/// - `cookie_file` could not be read, or has no usable cookie.
+/// - The saved cookie could not be loaded.
+pub fn ytm_cookie(cookie_file: Option<&Path>) -> anyhow::Result<Option<String>> {
+    if let Some(cookie) = given_ytm_cookie(cookie_file)? {
+        return Ok(Some(cookie));
+    }
+    secrets::load_str(YTM_DATA_CONFIG_NAME)
+}
+
+/// The ytmusic cookie from `SPTFYDL_YTM_COOKIE` or `cookie_file`, if either is given. These are never saved.
+///
+/// The cookie can be in any format [`import_cookie`] reads, like a `cookies.txt` or copied headers.
+///
+/// # Errors
+///
+/// `cookie_file` could not be read, or has no usable cookie.
+pub fn given_ytm_cookie(cookie_file: Option<&Path>) -> anyhow::Result<Option<String>> {
+    if let Ok(cookie) = env::var("SPTFYDL_YTM_COOKIE") {
+        import_cookie(&cookie)
+            .context("reading the ytm cookie from SPTFYDL_YTM_COOKIE")
+            .map(Some)
+    } else if let Some(path) = cookie_file {
+        let cookie = fs::read_to_string(path)
+            .with_context(|| format!("reading the ytm cookie from {}", path.display()))?;
+        import_cookie(&cookie)
+            .with_context(|| format!("reading the ytm cookie from {}", path.display()))
+            .map(Some)
     } else {
-        info!("no saved ytm cookie, need input.");
-        info!(
-            "please go to https://music.youtube.com and copy-paste your headers to an authenticatied POST request. (https://ytmusicapi.readthedocs.io/en/stable/setup/browser.html#copy-authentication-headers)"
-        );
+        Ok(None)
+    }
+}
 
-        if no_interaction {
-            return Err(anyhow!(
-                "required interaction to set cookie but --no-interaction was set."
-            ));
+/// Ask for the headers of a ytmusic request, or the path to a `cookies.txt` or `browser.json`, on `stdin`,
+/// returning their cookie as a `Cookie: ` header line.
+///
+/// # Errors
+///
+/// - `stdin` could not be read, or ended before a usable cookie was given.
+/// - The given file could not be read, or has no usable cookie.
+pub fn prompt_ytm_cookie() -> anyhow::Result<String> {
+    info!(
+        "please go to https://music.youtube.com and copy-paste your headers to an authenticatied POST request. (https://ytmusicapi.readthedocs.io/en/stable/setup/browser.html#copy-authentication-headers)"
+    );
+    info!("or give the path to a cookies.txt or a browser.json");
+
+    print!("input: ");
+    let _ = stdout().flush();
+    let mut input = String::new();
+    for line in stdin().lines() {
+        let line = line?;
+        if input.trim().is_empty() && Path::new(line.trim()).is_file() {
+            let path = line.trim();
+            let contents = fs::read_to_string(path)
+                .with_context(|| format!("reading the ytm cookie from {path}"))?;
+            return import_cookie(&contents)
+                .with_context(|| format!("reading the ytm cookie from {path}"));
         }
 
-        print!("input: ");
-        let _ = stdout().flush();
-        let cookie = stdin()
-            .lines()
-            .find(|l| {
-                l.as_ref().is_ok_and(|l| {
-                    l.starts_with("Cookie: ") || l.trim_ascii_start().starts_with("\"cookie:")
-                })
-            })
-            .expect("waiting forever for line")?;
-
-        if let Err(err) = save_str(&cookie, YTM_DATA_CONFIG_NAME) {
-            warn!("failed to save yt cookie: {err}");
+        input.push_str(&line);
+        input.push('\n');
+        // pasted headers are read up to their cookie
+        if let Ok(cookie) = import_cookie(&input) {
+            return Ok(cookie);
         }
+    }
+
+    import_cookie(&input).context("reading the ytm cookie from stdin")
+}
 
-        Ok(cookie)
+/// The secret file the credentials of the spotify `account` are saved in.
+#[must_use]
+pub fn oauth_config_name(account: &str) -> String {
+    if account == DEFAULT_ACCOUNT {
+        "spotify_oauth.yaml".to_string()
+    } else {
+        format!("spotify_oauth.{account}.yaml")
+    }
+}
+
+/// The secret file the access token of the spotify `account` is cached in.
+#[must_use]
+pub fn token_config_name(account: &str) -> String {
+    if account == DEFAULT_ACCOUNT {
+        "spotify_token.yaml".to_string()
+    } else {
+        format!("spotify_token.{account}.yaml")
+    }
+}
+
+/// The spotify accounts with saved credentials, sorted.
+///
+/// # Errors
+///
+/// The config dir could not be read.
+pub fn saved_accounts() -> anyhow::Result<Vec<String>> {
+    let mut accounts: Vec<_> = secrets::find("spotify_oauth")?
+        .into_iter()
+        .filter_map(|name| {
+            let account = name.strip_prefix("spotify_oauth")?.strip_suffix(".yaml")?;
+            if account.is_empty() {
+                Some(DEFAULT_ACCOUNT.to_string())
+            } else {
+                account.strip_prefix('.').map(ToString::to_string)
+            }
+        })
+        .collect();
+    accounts.sort_unstable();
+    Ok(accounts)
+}
+
+/// Check that `account` can be used in file names.
+///
+/// # Errors
+///
+/// The name is empty, or has characters other than ascii letters, digits, `-` and `_`.
+pub fn validate_account(account: &str) -> anyhow::Result<()> {
+    if account.is_empty()
+        || !account
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+    {
+        return Err(anyhow!(
+            "invalid account name `{account}`: use only letters, digits, `-` and `_`"
+        ));
+    }
+    Ok(())
+}
+
+/// Get the cached [`AccessToken`] of `account`, or a new one if it's missing or expired.
+///
+/// # Errors
+///
+/// This function fails if we could not get a new [`AccessToken`].
+pub async fn spotify_token(account: &str, id: &str, secret: &str) -> anyhow::Result<AccessToken> {
+    match secrets::load::<AccessToken>(&token_config_name(account)) {
+        Ok(Some(token)) if !token.expired() => {
+            debug!("got spotify token from cache");
+            Ok(token)
+        }
+        _ => request_token_and_save(account, id, secret).await,
     }
 }
 
@@ -459,13 +1004,17 @@ fn get_cookies(no_interaction: bool) -> anyhow::Result<String> {
 ///
 /// This function fails if we could not get a new [`AccessToken`].
 #[inline]
-pub async fn request_token_and_save(id: &str, secret: &str) -> anyhow::Result<AccessToken> {
+pub async fn request_token_and_save(
+    account: &str,
+    id: &str,
+    secret: &str,
+) -> anyhow::Result<AccessToken> {
     debug!("requesting new spotify access token");
     let Some(access_token) = AccessToken::get(id, secret).await else {
         return Err(anyhow!("could not get access token"));
     };
 
-    if let Err(err) = save(&access_token, SPOTIFY_TOKEN_CONFIG_NAME) {
+    if let Err(err) = secrets::save(&access_token, &token_config_name(account)) {
         warn!("failed to save new access token: {err}");
     } else {
         debug!("saved new access token");
@@ -12,11 +12,14 @@ use std::{
 };
 
 use anyhow::anyhow;
-use reqwest::IntoUrl;
+use reqwest::{IntoUrl, StatusCode};
 use serde::{Deserialize, Serialize};
-use tracing::debug;
+use tracing::{debug, warn};
 
-use crate::{CLIENT, IterExt};
+use crate::{
+    CLIENT, IterExt,
+    spotify::{ReleaseDate, Tokens},
+};
 
 /// Parse the spotify id from `url` and get a list of [`SpotifyTrack`]s and the name (of the playlist or album, if `url` is one.)
 ///
@@ -28,7 +31,7 @@ use crate::{CLIENT, IterExt};
 /// - We failed to run [`find_track`], [`find_playlist_tracks`], or [`find_album_tracks`].
 pub async fn get_from_url(
     url: impl IntoUrl,
-    access_token: impl AsRef<str>,
+    tokens: &Tokens,
 ) -> anyhow::Result<(Vec<SpotifyTrack>, Option<String>)> {
     let url = url.into_url()?;
 
@@ -42,12 +45,12 @@ pub async fn get_from_url(
     };
 
     if url.path().starts_with("/track") {
-        Ok((vec![find_track(id, access_token).await?], None))
+        Ok((vec![find_track(id, tokens).await?], None))
     } else if url.path().starts_with("/playlist") {
-        let (tracks, name) = find_playlist_tracks(id, access_token).await?;
+        let (tracks, name) = find_playlist_tracks(id, tokens).await?;
         Ok((tracks, Some(name)))
     } else if url.path().starts_with("/album") {
-        let (tracks, name) = find_album_tracks(id, access_token).await?;
+        let (tracks, name) = find_album_tracks(id, tokens).await?;
         Ok((tracks, Some(name)))
     } else {
         Err(anyhow!("spotify url was not a track, album, or a playlist"))
@@ -66,6 +69,7 @@ pub struct SpotifyTrack {
     /// use [`get_artists`] to get the actual artist info
     pub artists: Vec<SimplifiedArtist>,
     pub disc_number: u32,
+    pub duration_ms: u32,
     pub explicit: bool,
     pub external_ids: Option<ExternalIds>,
     pub track_number: u32,
@@ -74,9 +78,20 @@ pub struct SpotifyTrack {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Image {
     url: String,
+    #[serde(default)]
+    width: Option<u32>,
+    #[serde(default)]
+    height: Option<u32>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl Image {
+    /// The number of pixels, 0 if unknown.
+    fn area(&self) -> u64 {
+        u64::from(self.width.unwrap_or(0)) * u64::from(self.height.unwrap_or(0))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct ExternalIds {
     #[serde(default)]
     pub isrc: Option<String>,
@@ -97,7 +112,7 @@ pub struct SimplifiedArtist {
 pub struct SpotifyArtist {
     pub name: String,
     pub genres: Vec<String>,
-    id: String,
+    pub id: String,
 }
 
 impl Debug for SpotifyArtist {
@@ -106,37 +121,121 @@ impl Debug for SpotifyArtist {
     }
 }
 
+/// Select fields of a track's album.
+#[derive(Debug)]
+pub struct AlbumInfo {
+    pub name: String,
+    /// The url of the largest image.
+    pub cover_url: Option<String>,
+    pub release_date: Option<ReleaseDate>,
+    /// The year of the performance copyright, if it's before `release_date`.
+    pub original_year: Option<u16>,
+    pub total_tracks: u32,
+    /// Only known when downloading a whole album.
+    pub total_discs: Option<u32>,
+    pub artists: Vec<String>,
+    pub id: Option<String>,
+    /// `album`, `single` or `compilation`
+    pub album_type: Option<String>,
+    /// Only known for full albums, see [`bulk_albums`].
+    pub label: Option<String>,
+    /// Only known for full albums.
+    pub copyright: Option<String>,
+    /// Only known for full albums.
+    pub upc: Option<String>,
+}
+
 impl SpotifyTrack {
     // is an associated function to allow partial moves
-    /// Returns (`album_name`, `cover_url`, `release_date`, `total_tracks`).
-    ///
-    /// Will be `None` if `album` is `None`.
+    /// Returns the [`AlbumInfo`] of `album`.
     ///
-    /// # Panics
-    ///
-    /// Will panic if `album` is not a valid `Album` as defined in the function.
+    /// Will be `None` if `album` is `None`, or is not a valid `Album` as defined in the function.
     #[must_use]
-    pub fn extract_album(
-        album: Option<serde_json::Value>,
-    ) -> Option<(String, String, String, u32)> {
-        // used here only to find the image url
+    pub fn extract_album(album: Option<serde_json::Value>) -> Option<AlbumInfo> {
         #[derive(Deserialize)]
         struct Album {
             name: String,
             images: Vec<Image>,
             release_date: String,
+            #[serde(default)]
+            release_date_precision: Option<String>,
             total_tracks: u32,
+            #[serde(default)]
+            artists: Vec<SimplifiedArtist>,
+            /// not from spotify, added by [`find_album_tracks`]
+            #[serde(default)]
+            total_discs: Option<u32>,
+            #[serde(default)]
+            id: Option<String>,
+            #[serde(default, rename = "album_type")]
+            kind: Option<String>,
+            #[serde(default)]
+            label: Option<String>,
+            #[serde(default)]
+            copyrights: Vec<Copyright>,
+            #[serde(default)]
+            external_ids: Option<AlbumIds>,
         }
 
-        let mut album: Album = serde_json::from_value(album?).ok()?;
-        let cover_url = album.images.swap_remove(0).url;
+        #[derive(Deserialize)]
+        struct Copyright {
+            text: String,
+            /// `C` for copyright, `P` for performance copyright
+            r#type: String,
+        }
 
-        Some((
-            album.name,
+        #[derive(Deserialize)]
+        struct AlbumIds {
+            #[serde(default)]
+            upc: Option<String>,
+        }
+
+        let album: Album = serde_json::from_value(album?).ok()?;
+        let cover_url = album
+            .images
+            .into_iter()
+            .max_by_key(Image::area)
+            .map(|i| i.url);
+        let copyright = album
+            .copyrights
+            .iter()
+            .find(|c| c.r#type == "C")
+            .or(album.copyrights.first())
+            .map(|c| c.text.clone());
+        let release_date =
+            ReleaseDate::parse(&album.release_date, album.release_date_precision.as_deref());
+        // reissues keep the performance copyright of the original recording, like `℗ 1979 ...`
+        let original_year = album
+            .copyrights
+            .iter()
+            .filter(|c| c.r#type == "P")
+            .find_map(|c| first_year(&c.text))
+            .filter(|year| release_date.is_some_and(|date| *year < date.year));
+
+        Some(AlbumInfo {
+            name: album.name,
             cover_url,
-            album.release_date,
-            album.total_tracks,
-        ))
+            release_date,
+            original_year,
+            total_tracks: album.total_tracks,
+            total_discs: album.total_discs,
+            artists: album.artists.into_iter().filter_map(|a| a.name).collect(),
+            id: album.id,
+            album_type: album.kind,
+            label: album.label,
+            copyright,
+            upc: album.external_ids.and_then(|ids| ids.upc),
+        })
+    }
+
+    /// The id of `self.album`, if only its simplified version is known.
+    #[must_use]
+    pub fn partial_album_id(&self) -> Option<&str> {
+        let album = self.album.as_ref()?;
+        if album.get("label").is_some() {
+            return None;
+        }
+        album.get("id")?.as_str()
     }
 }
 
@@ -153,6 +252,14 @@ impl Debug for SpotifyTrack {
     }
 }
 
+/// The first plausible year in `text`, a standalone run of 4 digits.
+fn first_year(text: &str) -> Option<u16> {
+    text.split(|c: char| !c.is_ascii_digit())
+        .filter(|digits| digits.len() == 4)
+        .filter_map(|digits| digits.parse().ok())
+        .find(|year| (1800..=2200).contains(year))
+}
+
 /// Turn [`SimplifiedArtist`]s into [`SpotifyArtist`]s. Does bulk requests, chunking by 50.
 ///
 /// # Errors
@@ -164,12 +271,26 @@ impl Debug for SpotifyTrack {
 /// Should never panic.
 pub async fn bulk_artists(
     from: &[SimplifiedArtist],
-    access_token: &str,
+    tokens: &Tokens,
 ) -> anyhow::Result<Vec<SpotifyArtist>> {
-    let mut artists = bulk_many_artists(&[&from.to_vec()], access_token).await?;
+    let mut artists = bulk_many_artists(&[&from.to_vec()], tokens).await?;
     Ok(artists.pop().unwrap())
 }
 
+impl SpotifyArtist {
+    /// A [`SpotifyArtist`] without genres, for when the full artist could not be found.
+    ///
+    /// Returns `None` if `artist` has no name or id.
+    #[must_use]
+    pub fn from_simplified(artist: &SimplifiedArtist) -> Option<Self> {
+        Some(Self {
+            name: artist.name.clone()?,
+            genres: Vec::new(),
+            id: artist.id.clone()?,
+        })
+    }
+}
+
 impl PartialEq<SimplifiedArtist> for SpotifyArtist {
     fn eq(&self, other: &SimplifiedArtist) -> bool {
         Some(&self.id) == other.id.as_ref()
@@ -187,7 +308,7 @@ impl PartialEq<SimplifiedArtist> for SpotifyArtist {
 /// Will fail if any artist could not be found, or if any request fails to be sent.
 pub async fn bulk_many_artists(
     artist_arrays: &[&Vec<SimplifiedArtist>],
-    access_token: &str,
+    tokens: &Tokens,
 ) -> anyhow::Result<Vec<Vec<SpotifyArtist>>> {
     const ARTIST_API: &str = "https://api.spotify.com/v1/artists";
 
@@ -208,7 +329,7 @@ pub async fn bulk_many_artists(
                 .filter_map(|a| a.id.as_ref())
                 .join(",");
             let resp: SpotifyArtists =
-                get_resp(&format!("{ARTIST_API}/?ids={ids}"), access_token).await?;
+                get_resp(&format!("{ARTIST_API}/?ids={ids}"), tokens).await?;
             all_artists.extend(resp.artists);
         }
     }
@@ -229,30 +350,70 @@ pub async fn bulk_many_artists(
     Ok(result)
 }
 
-async fn bulk_tracks(
-    tracks: &[SpotifyTrack],
-    access_token: &str,
-) -> anyhow::Result<Vec<SpotifyTrack>> {
+/// Get the full tracks of `ids`. Does bulk requests, chunking by 50.
+///
+/// Tracks that could not be found are `None`. Order is preserved.
+///
+/// # Errors
+///
+/// See [`get_resp`].
+pub async fn bulk_tracks(
+    ids: &[&str],
+    tokens: &Tokens,
+) -> anyhow::Result<Vec<Option<SpotifyTrack>>> {
     const TRACK_API: &str = "https://api.spotify.com/v1/tracks";
 
     #[derive(Deserialize)]
     struct Tracks {
-        tracks: Vec<SpotifyTrack>,
+        tracks: Vec<Option<SpotifyTrack>>,
     }
 
-    let mut full_tracks = Vec::with_capacity(tracks.len());
-    for track in tracks.chunks(50) {
-        let ids = track.iter().map(|t| &t.id).join(",");
-        let resp: Tracks = get_resp(&format!("{TRACK_API}/?ids={ids}"), access_token).await?;
+    let mut full_tracks = Vec::with_capacity(ids.len());
+    for chunk in ids.chunks(50) {
+        let ids = chunk.join(",");
+        let resp: Tracks = get_resp(&format!("{TRACK_API}/?ids={ids}"), tokens).await?;
         full_tracks.extend(resp.tracks);
     }
 
     Ok(full_tracks)
 }
 
+/// Get the full albums of `ids` as json, without their tracks. Does bulk requests, chunking by 20.
+///
+/// Albums that could not be found are skipped.
+///
+/// # Errors
+///
+/// See [`get_resp`].
+pub async fn bulk_albums(ids: &[&str], tokens: &Tokens) -> anyhow::Result<Vec<serde_json::Value>> {
+    const ALBUM_API: &str = "https://api.spotify.com/v1/albums";
+
+    #[derive(Deserialize)]
+    struct Albums {
+        albums: Vec<Option<serde_json::Value>>,
+    }
+
+    let mut albums = Vec::with_capacity(ids.len());
+    for chunk in ids.chunks(20) {
+        let ids = chunk.join(",");
+        let resp: Albums = get_resp(&format!("{ALBUM_API}/?ids={ids}"), tokens).await?;
+        albums.extend(resp.albums.into_iter().flatten().map(|mut album| {
+            // can be big, and are not needed
+            if let Some(album) = album.as_object_mut() {
+                album.remove("tracks");
+            }
+            album
+        }));
+    }
+
+    Ok(albums)
+}
+
 pub static REQUESTS: AtomicU16 = AtomicU16::new(0);
 
-/// Get `url`, parsing as json to `T`, using `access_token` for authorization.
+/// Get `url`, parsing as json to `T`, using `tokens` for authorization.
+///
+/// When an account is rate limited, the request is sent again with the next one, until each was tried.
 ///
 /// # Errors
 ///
@@ -260,9 +421,21 @@ pub static REQUESTS: AtomicU16 = AtomicU16::new(0);
 /// - We could not send the request to `url`.
 /// - The request was not successful.
 /// - We could not deserialize the response as json to `T`.
-async fn get_resp<T: for<'a> Deserialize<'a>>(url: &str, access_token: &str) -> anyhow::Result<T> {
-    let resp = CLIENT.get(url).bearer_auth(access_token).send().await?;
-    REQUESTS.fetch_add(1, Ordering::Relaxed);
+async fn get_resp<T: for<'a> Deserialize<'a>>(url: &str, tokens: &Tokens) -> anyhow::Result<T> {
+    let mut tries = 0;
+    let resp = loop {
+        let (index, account, token) = tokens.current();
+        let resp = CLIENT.get(url).bearer_auth(token).send().await?;
+        REQUESTS.fetch_add(1, Ordering::Relaxed);
+        tries += 1;
+
+        if resp.status() == StatusCode::TOO_MANY_REQUESTS && tries < tokens.len() {
+            warn!("spotify account `{account}` is rate limited, switching accounts");
+            tokens.rotate(index);
+            continue;
+        }
+        break resp;
+    };
 
     if !resp.status().is_success() {
         return Err(anyhow!("got {}: {:?}", resp.status(), resp.text().await));
@@ -1,19 +1,16 @@
+use anyhow::anyhow;
 use serde::Deserialize;
 use serde_json::json;
 use tracing::info;
 
-use crate::IterExt;
+use crate::{IterExt, spotify::Tokens};
 
-use super::{Image, SimplifiedArtist, SpotifyTrack, bulk_tracks, get_resp};
+use super::{SimplifiedArtist, SpotifyTrack, bulk_tracks, get_resp};
 
 #[derive(Deserialize, Debug)]
 struct Album {
     name: String,
     artists: Vec<SimplifiedArtist>,
-    tracks: AlbumTracks,
-    total_tracks: u32,
-    release_date: String,
-    images: Vec<Image>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -21,7 +18,7 @@ struct AlbumTracks {
     items: Vec<SpotifyTrack>,
 }
 
-/// Find an album's tracks by its `id` using `access_token` for authorization.
+/// Find an album's tracks by its `id` using `tokens` for authorization.
 ///
 /// # Errors
 ///
@@ -32,27 +29,33 @@ struct AlbumTracks {
 /// Will panic if the `external_id` of any track could not be found.
 pub async fn find_album_tracks(
     id: impl AsRef<str>,
-    access_token: impl AsRef<str>,
+    tokens: &Tokens,
 ) -> anyhow::Result<(Vec<SpotifyTrack>, String)> {
     const ALBUM_API: &str = "https://api.spotify.com/v1/albums";
 
     let id = id.as_ref();
-    let access_token = access_token.as_ref();
 
     info!("finding album id `{id}`");
 
-    let resp: Album = get_resp(&format!("{ALBUM_API}/{id}"), access_token).await?;
+    let mut album_data: serde_json::Value = get_resp(&format!("{ALBUM_API}/{id}"), tokens).await?;
 
-    let album_data = json!({
-        "total_tracks": resp.total_tracks,
-        "release_date": resp.release_date,
-        "images": resp.images,
-        "name": resp.name,
-    });
+    // the tracks are not needed in each track's album
+    let tracks = album_data
+        .as_object_mut()
+        .and_then(|album| album.remove("tracks"))
+        .ok_or_else(|| anyhow!("album had no tracks"))?;
+    let mut tracks = serde_json::from_value::<AlbumTracks>(tracks)?.items;
+    let resp: Album = serde_json::from_value(album_data.clone())?;
 
-    let mut tracks = resp.tracks.items;
+    let total_discs = tracks.iter().map(|t| t.disc_number).max();
+    album_data["total_discs"] = json!(total_discs);
 
-    let full_tracks = bulk_tracks(&tracks, access_token).await?;
+    let ids: Vec<&str> = tracks.iter().map(|t| t.id.as_str()).collect();
+    let full_tracks = bulk_tracks(&ids, tokens)
+        .await?
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| anyhow!("some of the album's tracks could not be found"))?;
 
     assert_eq!(tracks.len(), full_tracks.len());
 
@@ -1,6 +1,8 @@
 use serde::Deserialize;
 use tracing::{debug, info};
 
+use crate::spotify::Tokens;
+
 use super::{ExternalIds, SimplifiedArtist, SpotifyTrack, get_resp};
 
 #[derive(Deserialize, Debug)]
@@ -37,6 +39,8 @@ struct PlaylistTrack {
     #[serde(default)]
     pub disc_number: Option<u32>,
     #[serde(default)]
+    pub duration_ms: Option<u32>,
+    #[serde(default)]
     pub explicit: Option<bool>,
     #[serde(default)]
     pub external_ids: Option<ExternalIds>,
@@ -52,6 +56,7 @@ impl PlaylistTrack {
             album: self.album,
             artists: self.artists?,
             disc_number: self.disc_number?,
+            duration_ms: self.duration_ms?,
             explicit: self.explicit?,
             external_ids: self.external_ids,
             track_number: self.track_number?,
@@ -70,23 +75,22 @@ struct PlaylistPagination {
     items: Vec<PlaylistItem>,
 }
 
-/// Find a playlist's tracks by its `id` using `access_token` for authorization.
+/// Find a playlist's tracks by its `id` using `tokens` for authorization.
 ///
 /// # Errors
 ///
 /// See [`get_resp`].
 pub async fn find_playlist_tracks(
     id: impl AsRef<str>,
-    access_token: impl AsRef<str>,
+    tokens: &Tokens,
 ) -> anyhow::Result<(Vec<SpotifyTrack>, String)> {
     const PLAYLIST_API: &str = "https://api.spotify.com/v1/playlists";
 
     let id = id.as_ref();
-    let access_token = access_token.as_ref();
 
     info!("finding playlist id `{id}`");
 
-    let resp: Playlist = get_resp(&format!("{PLAYLIST_API}/{id}"), access_token).await?;
+    let resp: Playlist = get_resp(&format!("{PLAYLIST_API}/{id}"), tokens).await?;
 
     let mut tracks: Vec<SpotifyTrack> = Vec::with_capacity(resp.tracks.total as usize);
 
@@ -102,7 +106,7 @@ pub async fn find_playlist_tracks(
     while let Some(cur_page) = next_page {
         info!("getting next page of tracks");
 
-        let cur_page: PlaylistPagination = get_resp(&cur_page, access_token).await?;
+        let cur_page: PlaylistPagination = get_resp(&cur_page, tokens).await?;
         debug!("got {} tracks", cur_page.items.len());
         tracks.extend(cur_page.items.into_iter().filter_map(|p| p.track?.into()));
         next_page = cur_page.next;
@@ -0,0 +1,59 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use super::AccessToken;
+
+/// The access tokens of one or more spotify accounts, by account name.
+///
+/// Requests use the current one, switching to the next when it is rate limited.
+/// Clones share the current account.
+#[derive(Debug, Clone)]
+pub struct Tokens {
+    accounts: Arc<[(String, AccessToken)]>,
+    current: Arc<AtomicUsize>,
+}
+
+impl Tokens {
+    /// Use the tokens of `accounts`, starting with the first.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `accounts` is empty.
+    #[must_use]
+    pub fn new(accounts: Vec<(String, AccessToken)>) -> Self {
+        assert!(!accounts.is_empty(), "need at least one account");
+        Self {
+            accounts: accounts.into(),
+            current: Arc::default(),
+        }
+    }
+
+    /// The index, account name and token to send requests with.
+    #[must_use]
+    pub fn current(&self) -> (usize, &str, &str) {
+        let index = self.current.load(Ordering::Relaxed);
+        let (account, token) = &self.accounts[index];
+        (index, account, token.as_ref())
+    }
+
+    /// The number of accounts.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// Switch to the next account, unless another request already switched away from `from`.
+    pub fn rotate(&self, from: usize) {
+        let next = (from + 1) % self.accounts.len();
+        let _ = self
+            .current
+            .compare_exchange(from, next, Ordering::Relaxed, Ordering::Relaxed);
+    }
+}
@@ -1,15 +1,12 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 
-use crate::{
-    IterExt,
-    spotify::search::{ExternalIds, SpotifyArtist, SpotifyTrack},
-};
+use crate::spotify::search::{ExternalIds, SpotifyArtist, SpotifyTrack};
 
 #[derive(Debug)]
 pub struct Extraction {
     pub tracks: Vec<(usize, Track)>,
     pub name: Option<String>,
-    /// guaranteed to be in range of `urls`
+    /// the track numbers of `tracks` that could be incorrect
     pub warnings: Vec<usize>,
     pub failures: usize,
 }
@@ -17,7 +14,10 @@ pub struct Extraction {
 impl Extraction {
     #[must_use]
     pub fn warnings(&self) -> Vec<&(usize, Track)> {
-        self.warnings.iter().map(|idx| &self.tracks[*idx]).collect()
+        self.tracks
+            .iter()
+            .filter(|(track_num, _)| self.warnings.contains(track_num))
+            .collect()
     }
 }
 
@@ -46,7 +46,7 @@ impl Track {
 }
 
 /// Contains select fields of [`SpotifyTrack`].
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Metadata {
     pub cover_url: Option<String>,
     pub disc_number: u32,
@@ -54,25 +54,118 @@ pub struct Metadata {
     pub artists: Vec<SpotifyArtist>,
     pub spotify_id: String,
     pub name: String,
+    pub duration_ms: u32,
     pub explicit: bool,
     pub external_ids: ExternalIds,
     pub track_number: u32,
     pub album_name: Option<String>,
+    pub album_artists: Vec<String>,
     pub album_tracks: Option<u32>,
-    /// y-m-d
-    pub release_date: Option<String>,
+    pub album_discs: Option<u32>,
+    pub album_id: Option<String>,
+    pub album_type: Option<String>,
+    pub label: Option<String>,
+    pub copyright: Option<String>,
+    pub upc: Option<String>,
+    pub release_date: Option<ReleaseDate>,
+    /// The year the album was first released, if it's a reissue.
+    pub original_year: Option<u16>,
+    /// How the track was found on ytmusic.
+    pub search_match: Option<SearchMatch>,
 }
 
-impl Metadata {
-    /// can turn `self.artists` into (`artists_tag_value`, `genres_tag_value`)
-    #[inline]
-    pub fn to_tag_values(artists: Vec<SpotifyArtist>, separator: &str) -> (String, String) {
-        let (artists, genres): (Vec<_>, Vec<_>) =
-            artists.into_iter().map(SpotifyArtist::into_tuple).unzip();
+/// A release date, only as precise as spotify knows it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReleaseDate {
+    pub year: u16,
+    pub month: Option<u8>,
+    /// Only known if `month` is.
+    pub day: Option<u8>,
+}
 
-        let genres = genres.iter().flatten().join("; ");
+impl ReleaseDate {
+    /// Parse a spotify `release_date`, like `1979`, `1979-06` or `1979-06-12`, keeping only what `precision` (`year`, `month` or `day`) allows.
+    ///
+    /// Returns `None` if the year is missing or zero.
+    #[must_use]
+    pub fn parse(date: &str, precision: Option<&str>) -> Option<Self> {
+        let mut parts = date.split('-').map(str::parse::<u16>);
+        let year = parts.next()?.ok().filter(|year| *year != 0)?;
+        let mut next = || {
+            parts
+                .next()?
+                .ok()
+                .and_then(|n| u8::try_from(n).ok())
+                .filter(|n| *n != 0)
+        };
+
+        let month = next().filter(|_| precision != Some("year"));
+        let day = month
+            .and_then(|_| next())
+            .filter(|_| !matches!(precision, Some("year" | "month")));
+
+        Some(Self { year, month, day })
+    }
+}
 
-        (artists.join(separator), genres)
+impl Display for ReleaseDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}", self.year)?;
+        if let Some(month) = self.month {
+            write!(f, "-{month:02}")?;
+            if let Some(day) = self.day {
+                write!(f, "-{day:02}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a track was found on ytmusic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub strategy: MatchStrategy,
+    /// The position of the chosen result, 1 being the best.
+    pub rank: usize,
+    /// The title of the chosen result.
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchStrategy {
+    /// Searched by isrc.
+    Isrc,
+    /// Searched by title and artists.
+    Query,
+}
+
+impl MatchStrategy {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Isrc => "isrc",
+            Self::Query => "query",
+        }
+    }
+}
+
+impl Metadata {
+    /// The names of `self.artists`.
+    #[must_use]
+    pub fn artist_names(&self) -> Vec<String> {
+        self.artists.iter().map(|a| a.name.clone()).collect()
+    }
+
+    /// The genres of all `self.artists`, without duplicates.
+    #[must_use]
+    pub fn genres(&self) -> Vec<String> {
+        let mut genres: Vec<String> = Vec::new();
+        for genre in self.artists.iter().flat_map(|a| &a.genres) {
+            if !genres.contains(genre) {
+                genres.push(genre.clone());
+            }
+        }
+        genres
     }
 }
 
@@ -84,31 +177,66 @@ impl SpotifyTrack {
     /// Will panic if `self.album` is `None`, or if `self.external_ids` is `None`.
     #[must_use]
     pub fn into_metadata(self, artists: Vec<SpotifyArtist>) -> Metadata {
-        let (album_name, cover_url, release_date, album_tracks) =
-            match SpotifyTrack::extract_album(self.album) {
-                Some(a) => (Some(a.0), Some(a.1), Some(a.2), Some(a.3)),
-                None => (None, None, None, None),
-            };
-        Metadata {
+        let mut metadata = Metadata {
             artists,
             disc_number: self.disc_number,
             name: self.name,
+            duration_ms: self.duration_ms,
             spotify_id: self.id,
             explicit: self.explicit,
             external_ids: self.external_ids.expect("must be some"),
             track_number: self.track_number,
-            album_name,
-            cover_url,
-            release_date,
-            album_tracks,
+            ..Default::default()
+        };
+
+        if let Some(album) = SpotifyTrack::extract_album(self.album) {
+            metadata.album_name = Some(album.name);
+            metadata.album_artists = album.artists;
+            metadata.cover_url = album.cover_url;
+            metadata.release_date = album.release_date;
+            metadata.original_year = album.original_year;
+            metadata.album_tracks = Some(album.total_tracks);
+            metadata.album_discs = album.total_discs;
+            metadata.album_id = album.id;
+            metadata.album_type = album.album_type;
+            metadata.label = album.label;
+            metadata.copyright = album.copyright;
+            metadata.upc = album.upc;
         }
+
+        metadata
     }
 }
 
-impl SpotifyArtist {
-    #[inline]
-    #[must_use]
-    pub fn into_tuple(self) -> (String, Vec<String>) {
-        (self.name, self.genres)
+#[cfg(test)]
+mod tests {
+    use super::ReleaseDate;
+
+    #[test]
+    fn release_date_precision() {
+        let date = |year, month, day| ReleaseDate { year, month, day };
+
+        assert_eq!(
+            ReleaseDate::parse("1979", Some("year")),
+            Some(date(1979, None, None))
+        );
+        assert_eq!(
+            ReleaseDate::parse("1979-06", Some("month")),
+            Some(date(1979, Some(6), None))
+        );
+        assert_eq!(
+            ReleaseDate::parse("1979-06-12", None),
+            Some(date(1979, Some(6), Some(12)))
+        );
+        // more precise than the precision allows
+        assert_eq!(
+            ReleaseDate::parse("1979-01-01", Some("year")),
+            Some(date(1979, None, None))
+        );
+        assert_eq!(ReleaseDate::parse("0000", Some("year")), None);
+        assert_eq!(ReleaseDate::parse("", None), None);
+
+        assert_eq!(date(1979, Some(6), None).to_string(), "1979-06");
+        assert_eq!(date(1979, Some(6), Some(2)).to_string(), "1979-06-02");
     }
 }
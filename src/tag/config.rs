@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use serde::Deserialize;
+
+use super::Template;
+
+/// What [`Tagger`](super::Tagger) can write, to choose from in [`TagConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    /// The track number and total.
+    Track,
+    /// The disc number and total.
+    Disc,
+    Date,
+    /// The year the album was first released, for reissues.
+    OriginalYear,
+    Isrc,
+    Upc,
+    Label,
+    Copyright,
+    AlbumType,
+    /// The explicit (parental advisory) flag.
+    Explicit,
+    Comment,
+    Cover,
+    Lyrics,
+    /// The spotify track, album and artist ids.
+    SpotifyIds,
+    YoutubeId,
+    /// How the track was matched on youtube.
+    Match,
+    #[serde(rename = "replaygain")]
+    ReplayGain,
+    /// The musicbrainz recording, release, release group and artist ids.
+    #[serde(rename = "musicbrainz_ids")]
+    MusicBrainzIds,
+}
+
+/// Which fields to write and how, from the `tagging` section of the config.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TagConfig {
+    /// If not empty, only these fields are written.
+    pub only: Vec<TagField>,
+    /// These fields are never written.
+    pub skip: Vec<TagField>,
+    /// If set, multiple values (like artists and genres) are joined with this, instead of being written as separate values.
+    pub separator: Option<String>,
+    /// Separators for specific fields, overriding `separator`.
+    pub separators: HashMap<TagField, String>,
+    /// Write these fields from templates instead, like `title: "{title} (feat. {featured})"`.
+    ///
+    /// Only the title, artist, album, album artist and comment can have templates.
+    pub templates: HashMap<TagField, Template>,
+    /// Renames spotify genres, ignoring case. Genres that end up the same are only written once.
+    pub genres: HashMap<String, String>,
+    /// Leave out genres that are not in `genres`.
+    pub drop_unmapped_genres: bool,
+}
+
+impl TagConfig {
+    /// Whether `field` should be written.
+    #[must_use]
+    pub fn writes(&self, field: TagField) -> bool {
+        (self.only.is_empty() || self.only.contains(&field)) && !self.skip.contains(&field)
+    }
+
+    /// The separator to join the values of `field` with, if any.
+    #[must_use]
+    pub fn separator(&self, field: TagField) -> Option<&str> {
+        self.separators
+            .get(&field)
+            .or(self.separator.as_ref())
+            .map(String::as_str)
+    }
+
+    /// Apply `self.genres` to `genres`, without duplicates.
+    #[must_use]
+    pub fn map_genres(&self, genres: Vec<String>) -> Vec<String> {
+        let mut mapped: Vec<String> = Vec::with_capacity(genres.len());
+        for genre in genres {
+            let target = self
+                .genres
+                .iter()
+                .find(|(from, _)| from.eq_ignore_ascii_case(&genre))
+                .map(|(_, to)| to.clone());
+            let genre = match target {
+                Some(target) => target,
+                None if self.drop_unmapped_genres => continue,
+                None => genre,
+            };
+            if !mapped.contains(&genre) {
+                mapped.push(genre);
+            }
+        }
+        mapped
+    }
+
+    /// Make sure only supported fields have templates.
+    ///
+    /// # Errors
+    ///
+    /// A field that can't have a template has one.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        const TEMPLATED: [TagField; 5] = [
+            TagField::Title,
+            TagField::Artist,
+            TagField::Album,
+            TagField::AlbumArtist,
+            TagField::Comment,
+        ];
+
+        if let Some(field) = self.templates.keys().find(|f| !TEMPLATED.contains(f)) {
+            return Err(anyhow!("{field:?} can't have a template"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::TagConfig;
+
+    #[test]
+    fn map_genres() {
+        let mut config = TagConfig {
+            genres: HashMap::from([
+                ("uk drill".to_string(), "Hip-Hop".to_string()),
+                ("Drill".to_string(), "Hip-Hop".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let genres = || {
+            ["uk drill", "drill", "grime"]
+                .map(ToString::to_string)
+                .to_vec()
+        };
+
+        assert_eq!(config.map_genres(genres()), ["Hip-Hop", "grime"]);
+
+        config.drop_unmapped_genres = true;
+        assert_eq!(config.map_genres(genres()), ["Hip-Hop"]);
+    }
+}
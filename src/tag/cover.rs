@@ -0,0 +1,248 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use lofty::picture::{MimeType, Picture, PictureType};
+use tokio::{io::AsyncWriteExt, process::Command, sync::OnceCell};
+use tracing::{debug, warn};
+
+use crate::{CLIENT, save_dir};
+
+/// Scale covers down with ffmpeg to fit in `max_size` by `max_size` pixels, re-encoding them as jpeg.
+#[derive(Debug, Clone)]
+pub struct CoverResize {
+    pub ffmpeg: PathBuf,
+    pub max_size: u32,
+}
+
+impl CoverResize {
+    async fn resize(&self, image: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let max = self.max_size;
+        let ffmpeg = Command::new(&self.ffmpeg)
+            .args(["-hide_banner", "-loglevel", "error", "-i", "pipe:0", "-vf"])
+            // never scales up
+            .arg(format!(
+                "scale='min({max},iw)':'min({max},ih)':force_original_aspect_ratio=decrease"
+            ))
+            .args(["-frames:v", "1", "-c:v", "mjpeg", "-q:v", "2"])
+            .args(["-f", "image2pipe", "pipe:1"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut ffmpeg = ffmpeg.map_err(|err| anyhow!("failed to spawn ffmpeg: {err}"))?;
+
+        let mut stdin = ffmpeg.stdin.take().expect("stdin is always captured");
+        // written separately so ffmpeg's output never fills up while we write
+        let writer = tokio::spawn(async move { stdin.write_all(&image).await });
+
+        let output = ffmpeg.wait_with_output().await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!(
+                "ffmpeg terminated with {}: {}",
+                output.status,
+                stderr.trim()
+            ));
+        }
+        writer.await??;
+
+        Ok(output.stdout)
+    }
+}
+
+/// A cover that is downloaded at most once, shared by everything that waits for it.
+type CoverCell = Arc<OnceCell<Arc<[u8]>>>;
+
+/// Downloads each cover only once per run, optionally keeping them on disk for later runs.
+#[derive(Debug, Default)]
+pub struct CoverCache {
+    covers: Mutex<HashMap<String, CoverCell>>,
+    on_disk: bool,
+    resize: Option<CoverResize>,
+    /// paths written to by [`CoverCache::write_to`]
+    written: Mutex<HashSet<PathBuf>>,
+}
+
+impl CoverCache {
+    /// If `on_disk`, covers are also kept in [`covers_dir`]. They are resized with `resize` after being loaded.
+    #[must_use]
+    pub fn new(on_disk: bool, resize: Option<CoverResize>) -> Self {
+        Self {
+            on_disk,
+            resize,
+            ..Default::default()
+        }
+    }
+
+    /// Get the cover at `url`, only downloading it if it's not cached.
+    ///
+    /// # Errors
+    ///
+    /// The cover could not be downloaded, read from disk, or resized.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal `Mutex` poisons.
+    pub async fn get(&self, url: &str) -> anyhow::Result<Arc<[u8]>> {
+        let cell = self
+            .covers
+            .lock()
+            .expect("should not be poisoned")
+            .entry(url.to_string())
+            .or_default()
+            .clone();
+
+        let cover = cell.get_or_try_init(|| self.load(url)).await?;
+        Ok(cover.clone())
+    }
+
+    /// Get the cover at `url` as a front cover [`Picture`].
+    ///
+    /// # Errors
+    ///
+    /// See [`CoverCache::get`].
+    pub async fn picture(&self, url: &str) -> anyhow::Result<Picture> {
+        let image = self.get(url).await?;
+
+        let mut picture = Picture::unchecked(image.to_vec()).pic_type(PictureType::CoverFront);
+        if let Some(mime_type) = mime_type(&image) {
+            picture = picture.mime_type(mime_type);
+        }
+
+        Ok(picture.build())
+    }
+
+    /// Write the cover at `url` to `path`, with the extension of its format, unless something was already
+    /// written there this run.
+    ///
+    /// Returns the path the cover was written to, if it was written.
+    ///
+    /// # Errors
+    ///
+    /// See [`CoverCache::get`] and [`fs::write`].
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal `Mutex` poisons.
+    pub async fn write_to(&self, url: &str, path: &Path) -> anyhow::Result<Option<PathBuf>> {
+        let new = self
+            .written
+            .lock()
+            .expect("should not be poisoned")
+            .insert(path.to_path_buf());
+        if !new {
+            return Ok(None);
+        }
+
+        let image = self.get(url).await?;
+        let path = with_image_extension(path, &image);
+        fs::write(&path, &image)?;
+        Ok(Some(path))
+    }
+
+    async fn load(&self, url: &str) -> anyhow::Result<Arc<[u8]>> {
+        let cached = if self.on_disk { cached_path(url) } else { None };
+
+        let image = if let Some(path) = cached.as_ref().filter(|p| p.exists()) {
+            debug!("found cover in {}", path.display());
+            fs::read(path)?
+        } else {
+            let image = CLIENT.get(url).send().await?.error_for_status()?;
+            let image = image.bytes().await?.to_vec();
+
+            if let Some(path) = cached.as_ref()
+                && let Err(err) = fs::write(path, &image)
+            {
+                warn!("failed to cache cover to {}: {err}", path.display());
+            }
+
+            image
+        };
+
+        let image = match self.resize.as_ref() {
+            Some(resize) => resize.resize(image).await?,
+            None => image,
+        };
+
+        Ok(image.into())
+    }
+}
+
+/// Where covers are kept with [`CoverCache::new`]'s `on_disk`, creating it if it doesn't exist.
+///
+/// # Panics
+///
+/// Will panic if the directory could not be created.
+#[must_use]
+pub fn covers_dir() -> PathBuf {
+    let dir = save_dir().join("covers");
+    if !dir.exists() {
+        fs::create_dir_all(&dir).expect("failed to create covers dir");
+    }
+    dir
+}
+
+/// The path to keep the cover at `url` in. Spotify's cover urls end with a unique id.
+fn cached_path(url: &str) -> Option<PathBuf> {
+    let id = url.rsplit('/').next()?;
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some(covers_dir().join(id))
+}
+
+/// `path` with the extension of `image`'s format, unless it already has one of that format.
+fn with_image_extension(path: &Path, image: &[u8]) -> PathBuf {
+    let extensions: &[&str] = match mime_type(image) {
+        Some(MimeType::Jpeg) => &["jpg", "jpeg"],
+        Some(MimeType::Png) => &["png"],
+        _ => return path.to_path_buf(),
+    };
+    let matches = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| extensions.contains(&ext.to_ascii_lowercase().as_str()));
+    if matches {
+        path.to_path_buf()
+    } else {
+        path.with_extension(extensions[0])
+    }
+}
+
+fn mime_type(image: &[u8]) -> Option<MimeType> {
+    if image.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(MimeType::Jpeg)
+    } else if image.starts_with(b"\x89PNG") {
+        Some(MimeType::Png)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::with_image_extension;
+
+    #[test]
+    fn cover_extensions() {
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0];
+        let png = *b"\x89PNG\r\n";
+        let ext = |name: &str, image: &[u8]| with_image_extension(Path::new(name), image);
+
+        assert_eq!(ext("cover.jpg", &jpeg), Path::new("cover.jpg"));
+        assert_eq!(ext("cover.JPEG", &jpeg), Path::new("cover.JPEG"));
+        assert_eq!(ext("cover.jpg", &png), Path::new("cover.png"));
+        assert_eq!(ext("folder", &png), Path::new("folder.png"));
+        // unknown formats keep the name
+        assert_eq!(ext("cover.jpg", b"GIF89a"), Path::new("cover.jpg"));
+    }
+}
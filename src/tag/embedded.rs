@@ -0,0 +1,234 @@
+use std::{fs::File, path::Path};
+
+use anyhow::anyhow;
+use lofty::{
+    config::ParseOptions,
+    file::{AudioFile, FileType, TaggedFileExt},
+    flac::FlacFile,
+    iff::{aiff::AiffFile, wav::WavFile},
+    mp4::Mp4File,
+    mpeg::MpegFile,
+    ogg::{OpusFile, SpeexFile, VorbisFile},
+    probe::Probe,
+};
+
+use super::{Field, TagReader, video_id};
+
+/// What [`Tagger`](super::Tagger) wrote to a file, found again when retagging.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Embedded {
+    pub track_id: Option<String>,
+    pub video_id: Option<String>,
+}
+
+impl Embedded {
+    /// The youtube url the file was downloaded from, if its video id is known.
+    #[must_use]
+    pub fn url(&self) -> Option<String> {
+        let id = self.video_id.as_ref()?;
+        Some(format!("https://youtube.com/watch?v={id}"))
+    }
+}
+
+/// Whether the file at `path` is a kind of audio file that can be tagged.
+#[must_use]
+pub fn is_audio_file(path: &Path) -> bool {
+    FileType::from_path(path).is_some()
+}
+
+/// Read the spotify track and youtube video ids from the tags of the file at `path`.
+///
+/// The ids are read from the tag native to the format, where [`Tagger`](super::Tagger) writes them.
+/// Falls back to the urls in the comment, for files tagged before the ids had their own tags.
+///
+/// # Errors
+///
+/// The file could not be read, or its type is unknown.
+pub fn read_embedded(path: &Path) -> anyhow::Result<Embedded> {
+    let file_type = Probe::open(path)?
+        .guess_file_type()?
+        .file_type()
+        .ok_or_else(|| anyhow!("unknown file type"))?;
+
+    let mut file = File::open(path)?;
+    let options = ParseOptions::new()
+        .read_properties(false)
+        .read_cover_art(false);
+    let embedded = match file_type {
+        FileType::Mpeg => from_tag(MpegFile::read_from(&mut file, options)?.id3v2()),
+        FileType::Wav => from_tag(WavFile::read_from(&mut file, options)?.id3v2()),
+        FileType::Aiff => from_tag(AiffFile::read_from(&mut file, options)?.id3v2()),
+        FileType::Flac => from_tag(FlacFile::read_from(&mut file, options)?.vorbis_comments()),
+        FileType::Opus => from_tag(Some(
+            OpusFile::read_from(&mut file, options)?.vorbis_comments(),
+        )),
+        FileType::Vorbis => from_tag(Some(
+            VorbisFile::read_from(&mut file, options)?.vorbis_comments(),
+        )),
+        FileType::Speex => from_tag(Some(
+            SpeexFile::read_from(&mut file, options)?.vorbis_comments(),
+        )),
+        FileType::Mp4 => from_tag(Mp4File::read_from(&mut file, options)?.ilst()),
+        _ => from_tag(lofty::read_from(&mut file)?.primary_tag()),
+    };
+    Ok(embedded)
+}
+
+fn from_tag(tag: Option<&impl TagReader>) -> Embedded {
+    let Some(tag) = tag else {
+        return Embedded::default();
+    };
+    let comment = tag.comment();
+    let words = || {
+        comment
+            .iter()
+            .flat_map(|comment| comment.split_whitespace())
+    };
+
+    let track_id = tag
+        .custom_value(Field::SpotifyTrackId)
+        .or_else(|| words().find_map(parse_track_id).map(ToString::to_string));
+    let video_id = tag
+        .custom_value(Field::YoutubeVideoId)
+        .or_else(|| words().find_map(video_id));
+
+    Embedded { track_id, video_id }
+}
+
+/// Parse a spotify track id from a track url, a `spotify:track:` uri, or the id itself.
+#[must_use]
+pub fn parse_track_id(track: &str) -> Option<&str> {
+    let id = if let Some((_, rest)) = track.split_once("open.spotify.com/track/") {
+        // drop `?si=...`
+        rest.split(['?', '#', '/']).next()?
+    } else if let Some(id) = track.strip_prefix("spotify:track:") {
+        id
+    } else {
+        track
+    };
+
+    // ids are base62
+    let valid = id.len() == 22 && id.chars().all(|c| c.is_ascii_alphanumeric());
+    valid.then_some(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+    };
+
+    use lofty::{
+        config::WriteOptions,
+        id3::v2::Id3v2Tag,
+        ogg::VorbisComments,
+        tag::{Accessor, TagExt},
+    };
+
+    use super::{
+        super::{Field, TagWriter},
+        Embedded, parse_track_id, read_embedded,
+    };
+
+    const TRACK_ID: &str = "4uLU6hMCjMI75M1A2tKUQC";
+
+    /// A file in the temp dir, removed when dropped, even if the test fails.
+    struct TempFile(PathBuf);
+
+    impl AsRef<Path> for TempFile {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// A flac file with only its stream info.
+    fn flac_file(name: &str) -> TempFile {
+        let mut flac = b"fLaC".to_vec();
+        // the last metadata block, stream info, 34 bytes long
+        flac.extend([0x80, 0x00, 0x00, 0x22]);
+        flac.extend([0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0]);
+        // 44100hz, 2 channels, 16 bits, unknown length, no md5
+        flac.extend([0x0a, 0xc4, 0x42, 0xf0, 0, 0, 0, 0]);
+        flac.extend([0; 16]);
+        temp_file(name, &flac)
+    }
+
+    /// An mp3 file with a few silent frames.
+    fn mp3_file(name: &str) -> TempFile {
+        let mut frame = vec![0xff, 0xfb, 0x90, 0x64];
+        frame.resize(417, 0);
+        temp_file(name, &frame.repeat(4))
+    }
+
+    fn temp_file(name: &str, contents: &[u8]) -> TempFile {
+        let file = TempFile(env::temp_dir().join(format!("sptfydl-{}-{name}", std::process::id())));
+        fs::write(&file, contents).unwrap();
+        file
+    }
+
+    fn read(file: &TempFile) -> Embedded {
+        read_embedded(file.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn track_ids() {
+        let id = TRACK_ID;
+        assert_eq!(parse_track_id(id), Some(id));
+        assert_eq!(parse_track_id(&format!("spotify:track:{id}")), Some(id));
+        assert_eq!(
+            parse_track_id(&format!("https://open.spotify.com/track/{id}?si=abc")),
+            Some(id)
+        );
+        assert_eq!(parse_track_id("https://open.spotify.com/album/xyz"), None);
+        assert_eq!(parse_track_id("downloaded"), None);
+    }
+
+    #[test]
+    fn native_tags() {
+        let expected = Embedded {
+            track_id: Some(TRACK_ID.to_string()),
+            video_id: Some("dQw4w9WgXcQ".to_string()),
+        };
+
+        let path = flac_file("native.flac");
+        let mut tag = VorbisComments::default();
+        tag.set_values(Field::SpotifyTrackId, vec![TRACK_ID.to_string()]);
+        tag.set_values(Field::YoutubeVideoId, vec!["dQw4w9WgXcQ".to_string()]);
+        tag.save_to_path(&path, WriteOptions::default()).unwrap();
+        assert_eq!(read(&path), expected);
+
+        let path = mp3_file("native.mp3");
+        let mut tag = Id3v2Tag::new();
+        tag.set_values(Field::SpotifyTrackId, vec![TRACK_ID.to_string()]);
+        tag.set_values(Field::YoutubeVideoId, vec!["dQw4w9WgXcQ".to_string()]);
+        tag.save_to_path(&path, WriteOptions::default()).unwrap();
+        assert_eq!(read(&path), expected);
+    }
+
+    #[test]
+    fn comment_urls() {
+        let path = mp3_file("comment.mp3");
+        let mut tag = Id3v2Tag::new();
+        tag.set_comment(format!(
+            "original spotify url: https://open.spotify.com/track/{TRACK_ID}\n\
+             downloaded from: https://youtube.com/watch?v=dQw4w9WgXcQ"
+        ));
+        tag.save_to_path(&path, WriteOptions::default()).unwrap();
+        assert_eq!(
+            read(&path),
+            Embedded {
+                track_id: Some(TRACK_ID.to_string()),
+                video_id: Some("dQw4w9WgXcQ".to_string()),
+            }
+        );
+
+        assert_eq!(read(&flac_file("untagged.flac")), Embedded::default());
+    }
+}
@@ -0,0 +1,72 @@
+use std::borrow::Cow;
+
+use lofty::{
+    TextEncoding,
+    id3::v2::{
+        Frame, FrameId, Id3v2Tag, TextInformationFrame, UniqueFileIdentifierFrame,
+        UnsynchronizedTextFrame,
+    },
+    picture::Picture,
+};
+
+use super::{Field, TagReader, TagWriter};
+
+/// The owner of musicbrainz ufid frames.
+const MUSICBRAINZ_OWNER: &str = "http://musicbrainz.org";
+
+impl TagWriter for Id3v2Tag {
+    // id3v2.4 separates multiple values with nulls
+    fn set_values(&mut self, field: Field, values: Vec<String>) {
+        let id = match field {
+            Field::Artist => "TPE1",
+            Field::AlbumArtist => "TPE2",
+            Field::Genre => "TCON",
+            Field::Isrc => "TSRC",
+            Field::Label => "TPUB",
+            Field::Copyright => "TCOP",
+            Field::OriginalYear => "TDOR",
+            // picard reads the recording id from a ufid frame
+            Field::MusicBrainzTrackId => {
+                let id = values.concat().into_bytes();
+                let frame = UniqueFileIdentifierFrame::new(MUSICBRAINZ_OWNER.to_string(), id);
+                self.insert(Frame::UniqueFileIdentifier(frame));
+                return;
+            }
+            other => {
+                let name = other.picard_name().unwrap_or(other.name());
+                self.insert_user_text(name.to_string(), values.join("\0"));
+                return;
+            }
+        };
+
+        let frame = TextInformationFrame::new(
+            FrameId::Valid(Cow::Borrowed(id)),
+            TextEncoding::UTF8,
+            values.join("\0"),
+        );
+        self.insert(Frame::Text(frame));
+    }
+
+    fn set_explicit(&mut self) {
+        // 1 is explicit
+        self.insert_user_text("ITUNESADVISORY".to_string(), "1".to_string());
+    }
+
+    fn set_lyrics(&mut self, lyrics: String) {
+        // the language is unknown
+        let frame =
+            UnsynchronizedTextFrame::new(TextEncoding::UTF8, *b"XXX", String::new(), lyrics);
+        self.insert(Frame::UnsynchronizedText(frame));
+    }
+
+    fn set_cover(&mut self, cover: Picture) {
+        self.insert_picture(cover);
+    }
+}
+
+impl TagReader for Id3v2Tag {
+    fn custom_value(&self, field: Field) -> Option<String> {
+        let text = self.get_user_text(field.picard_name().unwrap_or(field.name()))?;
+        text.split('\0').next().map(ToString::to_string)
+    }
+}
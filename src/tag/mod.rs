@@ -0,0 +1,497 @@
+pub mod config;
+pub mod cover;
+pub mod embedded;
+mod id3v2;
+mod mp4;
+pub mod replaygain;
+pub mod template;
+mod vorbis;
+
+pub use config::{TagConfig, TagField};
+pub use cover::{CoverCache, CoverResize};
+pub use embedded::{Embedded, is_audio_file, parse_track_id, read_embedded};
+pub use replaygain::{Gain, Loudness, ReplayGain, measure_loudness};
+pub use template::Template;
+
+use std::{
+    path::Path,
+    sync::{Arc, LazyLock},
+    time::Instant,
+};
+
+use anyhow::anyhow;
+use lofty::{
+    config::WriteOptions,
+    error::LoftyError,
+    file::FileType,
+    id3::v2::Id3v2Tag,
+    mp4::Ilst,
+    ogg::VorbisComments,
+    picture::Picture,
+    probe::Probe,
+    tag::{Accessor, ItemKey, ItemValue, Tag, TagExt, TagItem, items::Timestamp},
+};
+use reqwest::Url;
+use tracing::{debug, instrument};
+
+use crate::{musicbrainz::MusicBrainzIds, spotify::Metadata};
+
+/// A text field that can have multiple values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Artist,
+    AlbumArtist,
+    Genre,
+    Isrc,
+    Label,
+    Copyright,
+    Upc,
+    AlbumType,
+    SpotifyTrackId,
+    SpotifyAlbumId,
+    SpotifyArtistIds,
+    YoutubeVideoId,
+    MatchStrategy,
+    MatchRank,
+    TrackGain,
+    TrackPeak,
+    AlbumGain,
+    AlbumPeak,
+    MusicBrainzTrackId,
+    MusicBrainzArtistIds,
+    MusicBrainzAlbumId,
+    MusicBrainzReleaseGroupId,
+    MusicBrainzAlbumArtistIds,
+    OriginalYear,
+}
+
+impl Field {
+    /// The [`TagField`] that decides whether this is written.
+    fn tag_field(self) -> TagField {
+        match self {
+            Self::Artist => TagField::Artist,
+            Self::AlbumArtist => TagField::AlbumArtist,
+            Self::Genre => TagField::Genre,
+            Self::Isrc => TagField::Isrc,
+            Self::Label => TagField::Label,
+            Self::Copyright => TagField::Copyright,
+            Self::Upc => TagField::Upc,
+            Self::AlbumType => TagField::AlbumType,
+            Self::SpotifyTrackId | Self::SpotifyAlbumId | Self::SpotifyArtistIds => {
+                TagField::SpotifyIds
+            }
+            Self::YoutubeVideoId => TagField::YoutubeId,
+            Self::MatchStrategy | Self::MatchRank => TagField::Match,
+            Self::TrackGain | Self::TrackPeak | Self::AlbumGain | Self::AlbumPeak => {
+                TagField::ReplayGain
+            }
+            Self::MusicBrainzTrackId
+            | Self::MusicBrainzArtistIds
+            | Self::MusicBrainzAlbumId
+            | Self::MusicBrainzReleaseGroupId
+            | Self::MusicBrainzAlbumArtistIds => TagField::MusicBrainzIds,
+            Self::OriginalYear => TagField::OriginalYear,
+        }
+    }
+
+    /// The vorbis comment key of the field, also used for custom fields in other formats.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Artist => "ARTIST",
+            Self::AlbumArtist => "ALBUMARTIST",
+            Self::Genre => "GENRE",
+            Self::Isrc => "ISRC",
+            Self::Label => "LABEL",
+            Self::Copyright => "COPYRIGHT",
+            Self::Upc => "BARCODE",
+            Self::AlbumType => "RELEASETYPE",
+            Self::SpotifyTrackId => "SPOTIFY_TRACK_ID",
+            Self::SpotifyAlbumId => "SPOTIFY_ALBUM_ID",
+            Self::SpotifyArtistIds => "SPOTIFY_ARTIST_ID",
+            Self::YoutubeVideoId => "YOUTUBE_VIDEO_ID",
+            Self::MatchStrategy => "SPTFYDL_MATCH_STRATEGY",
+            Self::MatchRank => "SPTFYDL_MATCH_RANK",
+            Self::TrackGain => "REPLAYGAIN_TRACK_GAIN",
+            Self::TrackPeak => "REPLAYGAIN_TRACK_PEAK",
+            Self::AlbumGain => "REPLAYGAIN_ALBUM_GAIN",
+            Self::AlbumPeak => "REPLAYGAIN_ALBUM_PEAK",
+            // the track id is the recording's, as picard writes it
+            Self::MusicBrainzTrackId => "MUSICBRAINZ_TRACKID",
+            Self::MusicBrainzArtistIds => "MUSICBRAINZ_ARTISTID",
+            Self::MusicBrainzAlbumId => "MUSICBRAINZ_ALBUMID",
+            Self::MusicBrainzReleaseGroupId => "MUSICBRAINZ_RELEASEGROUPID",
+            Self::MusicBrainzAlbumArtistIds => "MUSICBRAINZ_ALBUMARTISTID",
+            Self::OriginalYear => "ORIGINALYEAR",
+        }
+    }
+
+    /// The name picard uses for musicbrainz fields in id3v2 and mp4 tags.
+    fn picard_name(self) -> Option<&'static str> {
+        match self {
+            Self::MusicBrainzTrackId => Some("MusicBrainz Track Id"),
+            Self::MusicBrainzArtistIds => Some("MusicBrainz Artist Id"),
+            Self::MusicBrainzAlbumId => Some("MusicBrainz Album Id"),
+            Self::MusicBrainzReleaseGroupId => Some("MusicBrainz Release Group Id"),
+            Self::MusicBrainzAlbumArtistIds => Some("MusicBrainz Album Artist Id"),
+            _ => None,
+        }
+    }
+}
+
+/// A kind of tag that [`Metadata`] can be written to.
+trait TagWriter: TagExt<Err = LoftyError> {
+    /// Set `field` to `values`, replacing its previous values. `values` is never empty.
+    fn set_values(&mut self, field: Field, values: Vec<String>);
+
+    /// Mark the track as explicit.
+    fn set_explicit(&mut self);
+
+    /// Set the unsynchronised lyrics, which can also be in the LRC format.
+    fn set_lyrics(&mut self, lyrics: String);
+
+    fn set_cover(&mut self, cover: Picture);
+}
+
+/// A kind of tag that [`Embedded`] ids can be read back from.
+trait TagReader: Accessor {
+    /// The first value of the custom `field`, where [`TagWriter::set_values`] writes it.
+    fn custom_value(&self, field: Field) -> Option<String>;
+}
+
+/// What was found for a track besides its spotify metadata.
+#[derive(Debug, Clone, Default)]
+pub struct Extras {
+    pub lyrics: Option<String>,
+    pub replaygain: Option<ReplayGain>,
+    pub musicbrainz: Option<MusicBrainzIds>,
+}
+
+/// Everything to write to a tag.
+struct Contents<'a> {
+    metadata: Metadata,
+    /// where the file was downloaded from, if known
+    url: Option<&'a str>,
+    cover: Option<Picture>,
+    extras: Extras,
+}
+
+/// The comment written when the config has no template for it.
+static DEFAULT_COMMENT: LazyLock<Template> = LazyLock::new(|| {
+    "
+    original spotify url: {spotify_url}[
+    downloaded from: {url}]
+
+    by sptfydl!
+    "
+    .parse()
+    .expect("valid template")
+});
+
+/// Writes tags to downloaded files, in the tag format native to each file.
+#[derive(Debug, Clone, Default)]
+pub struct Tagger {
+    pub config: TagConfig,
+    pub covers: Arc<CoverCache>,
+}
+
+impl Tagger {
+    /// Tag the file at `path` with `metadata` and `extras`. `url` is where the file was downloaded from, if known.
+    ///
+    /// # Errors
+    ///
+    /// - The file could not be read, or its type is unknown.
+    /// - The cover could not be downloaded.
+    /// - The tag could not be written.
+    #[instrument(skip(self, metadata, url, extras))]
+    pub async fn tag(
+        &self,
+        path: &Path,
+        metadata: Metadata,
+        url: Option<&str>,
+        extras: Extras,
+    ) -> anyhow::Result<()> {
+        let file_type = Probe::open(path)?
+            .guess_file_type()?
+            .file_type()
+            .ok_or_else(|| anyhow!("unknown file type"))?;
+
+        let cover = match metadata.cover_url.as_deref() {
+            Some(cover_url) if self.config.writes(TagField::Cover) => {
+                Some(self.covers.picture(cover_url).await?)
+            }
+            _ => None,
+        };
+
+        debug!("tagging {file_type:?} file");
+
+        let contents = Contents {
+            metadata,
+            url,
+            cover,
+            extras: Extras {
+                lyrics: extras
+                    .lyrics
+                    .filter(|_| self.config.writes(TagField::Lyrics)),
+                replaygain: extras
+                    .replaygain
+                    .filter(|_| self.config.writes(TagField::ReplayGain)),
+                musicbrainz: extras.musicbrainz,
+            },
+        };
+        match file_type {
+            FileType::Mpeg | FileType::Wav | FileType::Aiff => {
+                self.write(Id3v2Tag::new(), path, contents)
+            }
+            FileType::Flac | FileType::Opus | FileType::Vorbis | FileType::Speex => {
+                self.write(VorbisComments::default(), path, contents)
+            }
+            FileType::Mp4 => self.write(Ilst::default(), path, contents),
+            other => self.write(Tag::new(other.primary_tag_type()), path, contents),
+        }
+    }
+
+    fn write<T: TagWriter>(
+        &self,
+        mut tag: T,
+        path: &Path,
+        contents: Contents,
+    ) -> anyhow::Result<()> {
+        let Contents {
+            metadata,
+            url,
+            cover,
+            extras:
+                Extras {
+                    lyrics,
+                    replaygain,
+                    musicbrainz,
+                },
+        } = contents;
+
+        self.write_sources(&mut tag, &metadata, url);
+        self.write_names(&mut tag, &metadata, url);
+
+        if let Some(cover) = cover {
+            tag.set_cover(cover);
+        }
+        if let Some(lyrics) = lyrics {
+            tag.set_lyrics(lyrics);
+        }
+
+        if self.config.writes(TagField::Track) {
+            tag.set_track(metadata.track_number);
+            if let Some(album_tracks) = metadata.album_tracks {
+                tag.set_track_total(album_tracks);
+            }
+        }
+        if self.config.writes(TagField::Disc) {
+            tag.set_disk(metadata.disc_number);
+            if let Some(album_discs) = metadata.album_discs {
+                tag.set_disk_total(album_discs);
+            }
+        }
+
+        if metadata.explicit && self.config.writes(TagField::Explicit) {
+            tag.set_explicit();
+        }
+
+        if let Some(replaygain) = replaygain {
+            self.write_replaygain(&mut tag, replaygain);
+        }
+        if let Some(musicbrainz) = musicbrainz {
+            self.write_musicbrainz(&mut tag, musicbrainz);
+        }
+
+        if let Some(date) = metadata.release_date
+            && self.config.writes(TagField::Date)
+        {
+            tag.set_date(Timestamp {
+                year: date.year,
+                month: date.month,
+                day: date.day,
+                ..Default::default()
+            });
+        }
+
+        if self.config.writes(TagField::Comment) {
+            let comment = self.config.templates.get(&TagField::Comment);
+            tag.set_comment(comment.unwrap_or(&DEFAULT_COMMENT).render(&metadata, url));
+        }
+
+        let ids = [
+            (Field::Isrc, metadata.external_ids.isrc),
+            (Field::Upc, metadata.upc),
+            (Field::Label, metadata.label),
+            (Field::Copyright, metadata.copyright),
+            (Field::AlbumType, metadata.album_type),
+            (
+                Field::OriginalYear,
+                metadata.original_year.map(|year| year.to_string()),
+            ),
+        ];
+        for (field, value) in ids {
+            self.set_values(&mut tag, field, value.into_iter().collect());
+        }
+
+        let start = Instant::now();
+        tag.save_to_path(path, WriteOptions::default())?;
+        debug!("save took {:?}", start.elapsed());
+
+        Ok(())
+    }
+
+    /// Write the title, album, artists and genres, from templates if there are any.
+    fn write_names<T: TagWriter>(&self, tag: &mut T, metadata: &Metadata, url: Option<&str>) {
+        let templated = |field| {
+            let template = self.config.templates.get(&field)?;
+            Some(template.render(metadata, url))
+        };
+
+        if self.config.writes(TagField::Title) {
+            tag.set_title(templated(TagField::Title).unwrap_or_else(|| metadata.name.clone()));
+        }
+        if self.config.writes(TagField::Album)
+            && let Some(album) = templated(TagField::Album).or_else(|| metadata.album_name.clone())
+        {
+            tag.set_album(album);
+        }
+
+        let artists =
+            templated(TagField::Artist).map_or_else(|| metadata.artist_names(), |a| vec![a]);
+        self.set_values(tag, Field::Artist, artists);
+        let album_artists = templated(TagField::AlbumArtist)
+            .map_or_else(|| metadata.album_artists.clone(), |a| vec![a]);
+        self.set_values(tag, Field::AlbumArtist, album_artists);
+        self.set_values(tag, Field::Genre, self.config.map_genres(metadata.genres()));
+    }
+
+    /// Write where the track came from, and how it was found.
+    fn write_sources<T: TagWriter>(&self, tag: &mut T, metadata: &Metadata, url: Option<&str>) {
+        let artist_ids = metadata.artists.iter().map(|a| a.id.clone()).collect();
+        self.set_values(tag, Field::SpotifyArtistIds, artist_ids);
+        self.set_values(
+            tag,
+            Field::SpotifyTrackId,
+            vec![metadata.spotify_id.clone()],
+        );
+        if let Some(album_id) = metadata.album_id.clone() {
+            self.set_values(tag, Field::SpotifyAlbumId, vec![album_id]);
+        }
+        if let Some(video_id) = url.and_then(video_id) {
+            self.set_values(tag, Field::YoutubeVideoId, vec![video_id]);
+        }
+        if let Some(search_match) = &metadata.search_match {
+            let strategy = search_match.strategy.as_str().to_string();
+            self.set_values(tag, Field::MatchStrategy, vec![strategy]);
+            self.set_values(tag, Field::MatchRank, vec![search_match.rank.to_string()]);
+        }
+    }
+
+    fn write_replaygain<T: TagWriter>(&self, tag: &mut T, replaygain: ReplayGain) {
+        let ReplayGain { track, album } = replaygain;
+        self.set_values(tag, Field::TrackGain, vec![track.gain_value()]);
+        self.set_values(tag, Field::TrackPeak, vec![track.peak_value()]);
+        if let Some(album) = album {
+            self.set_values(tag, Field::AlbumGain, vec![album.gain_value()]);
+            self.set_values(tag, Field::AlbumPeak, vec![album.peak_value()]);
+        }
+    }
+
+    fn write_musicbrainz<T: TagWriter>(&self, tag: &mut T, ids: MusicBrainzIds) {
+        let ids = [
+            (
+                Field::MusicBrainzTrackId,
+                ids.recording.into_iter().collect(),
+            ),
+            (Field::MusicBrainzArtistIds, ids.artists),
+            (Field::MusicBrainzAlbumId, ids.release.into_iter().collect()),
+            (
+                Field::MusicBrainzReleaseGroupId,
+                ids.release_group.into_iter().collect(),
+            ),
+            (Field::MusicBrainzAlbumArtistIds, ids.album_artists),
+        ];
+        for (field, values) in ids {
+            self.set_values(tag, field, values);
+        }
+    }
+
+    /// Set `field` to `values` if it's written, joining them if it has a separator.
+    fn set_values<T: TagWriter>(&self, tag: &mut T, field: Field, values: Vec<String>) {
+        let tag_field = field.tag_field();
+        if values.is_empty() || !self.config.writes(tag_field) {
+            return;
+        }
+
+        match self.config.separator(tag_field) {
+            Some(separator) => tag.set_values(field, vec![values.join(separator)]),
+            None => tag.set_values(field, values),
+        }
+    }
+}
+
+/// The `v` parameter of a youtube `url`.
+#[must_use]
+pub fn video_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    url.query_pairs()
+        .find(|(key, _)| key == "v")
+        .map(|(_, id)| id.into_owned())
+}
+
+/// For formats without a specific writer; lofty decides how to store multiple values.
+impl TagWriter for Tag {
+    fn set_values(&mut self, field: Field, values: Vec<String>) {
+        let key = match field {
+            Field::Artist => ItemKey::TrackArtist,
+            Field::AlbumArtist => ItemKey::AlbumArtist,
+            Field::Genre => ItemKey::Genre,
+            Field::Isrc => ItemKey::Isrc,
+            Field::Label => ItemKey::Label,
+            Field::Copyright => ItemKey::CopyrightMessage,
+            Field::Upc => ItemKey::Barcode,
+            Field::TrackGain => ItemKey::ReplayGainTrackGain,
+            Field::TrackPeak => ItemKey::ReplayGainTrackPeak,
+            Field::AlbumGain => ItemKey::ReplayGainAlbumGain,
+            Field::AlbumPeak => ItemKey::ReplayGainAlbumPeak,
+            Field::MusicBrainzTrackId => ItemKey::MusicBrainzRecordingId,
+            Field::MusicBrainzArtistIds => ItemKey::MusicBrainzArtistId,
+            Field::MusicBrainzAlbumId => ItemKey::MusicBrainzReleaseId,
+            Field::MusicBrainzReleaseGroupId => ItemKey::MusicBrainzReleaseGroupId,
+            Field::MusicBrainzAlbumArtistIds => ItemKey::MusicBrainzReleaseArtistId,
+            Field::OriginalYear => ItemKey::OriginalReleaseDate,
+            other => {
+                let Some(key) = ItemKey::from_key(self.tag_type(), other.name()) else {
+                    debug!(field = other.name(), tag = ?self.tag_type(), "field not supported");
+                    return;
+                };
+                key
+            }
+        };
+
+        self.remove_key(key);
+        for value in values {
+            self.push(TagItem::new(key, ItemValue::Text(value)));
+        }
+    }
+
+    fn set_explicit(&mut self) {
+        // 1 is explicit
+        self.insert_text(ItemKey::ParentalAdvisory, "1".to_string());
+    }
+
+    fn set_cover(&mut self, cover: Picture) {
+        self.push_picture(cover);
+    }
+
+    fn set_lyrics(&mut self, lyrics: String) {
+        self.insert_text(ItemKey::Lyrics, lyrics);
+    }
+}
+
+impl TagReader for Tag {
+    fn custom_value(&self, field: Field) -> Option<String> {
+        let key = ItemKey::from_key(self.tag_type(), field.name())?;
+        self.get_string(key).map(ToString::to_string)
+    }
+}
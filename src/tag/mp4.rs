@@ -0,0 +1,65 @@
+use std::borrow::Cow;
+
+use lofty::{
+    mp4::{AdvisoryRating, Atom, AtomData, AtomIdent, Ilst},
+    picture::Picture,
+};
+
+use super::{Field, TagReader, TagWriter};
+
+impl TagWriter for Ilst {
+    // an atom can hold multiple values, though not every player reads them
+    fn set_values(&mut self, field: Field, values: Vec<String>) {
+        let ident = match field {
+            Field::Artist => AtomIdent::Fourcc(*b"\xa9ART"),
+            Field::AlbumArtist => AtomIdent::Fourcc(*b"aART"),
+            Field::Genre => AtomIdent::Fourcc(*b"\xa9gen"),
+            Field::Copyright => AtomIdent::Fourcc(*b"cprt"),
+            // players expect these in lowercase
+            Field::TrackGain => freeform("replaygain_track_gain"),
+            Field::TrackPeak => freeform("replaygain_track_peak"),
+            Field::AlbumGain => freeform("replaygain_album_gain"),
+            Field::AlbumPeak => freeform("replaygain_album_peak"),
+            other => freeform(other.picard_name().unwrap_or(other.name())),
+        };
+
+        if let Some(atom) =
+            Atom::from_collection(ident, values.into_iter().map(AtomData::UTF8).collect())
+        {
+            self.replace_atom(atom);
+        }
+    }
+
+    fn set_explicit(&mut self) {
+        self.set_advisory_rating(AdvisoryRating::Explicit);
+    }
+
+    fn set_lyrics(&mut self, lyrics: String) {
+        self.replace_atom(Atom::new(
+            AtomIdent::Fourcc(*b"\xa9lyr"),
+            AtomData::UTF8(lyrics),
+        ));
+    }
+
+    fn set_cover(&mut self, cover: Picture) {
+        self.insert_picture(cover);
+    }
+}
+
+impl TagReader for Ilst {
+    fn custom_value(&self, field: Field) -> Option<String> {
+        let atom = self.get(&freeform(field.picard_name().unwrap_or(field.name())))?;
+        atom.data().find_map(|data| match data {
+            AtomData::UTF8(text) => Some(text.clone()),
+            _ => None,
+        })
+    }
+}
+
+/// An iTunes freeform atom, `----:com.apple.iTunes:{name}`.
+fn freeform(name: &'static str) -> AtomIdent<'static> {
+    AtomIdent::Freeform {
+        mean: Cow::Borrowed("com.apple.iTunes"),
+        name: Cow::Borrowed(name),
+    }
+}
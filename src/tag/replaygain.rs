@@ -0,0 +1,197 @@
+use std::{path::Path, process::Stdio, time::Duration};
+
+use anyhow::anyhow;
+use tokio::process::Command;
+use tracing::debug;
+
+/// `ReplayGain` 2.0 aims for this loudness, in LUFS.
+const REFERENCE_LOUDNESS: f64 = -18.0;
+
+/// The loudness of a track, as measured by ffmpeg's `ebur128` filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    /// Integrated loudness, in LUFS.
+    pub integrated: f64,
+    /// True peak, in dBFS.
+    pub peak: f64,
+}
+
+impl Loudness {
+    /// The gain that brings this loudness to the `ReplayGain` reference.
+    #[must_use]
+    pub fn gain(self) -> Gain {
+        Gain {
+            gain: REFERENCE_LOUDNESS - self.integrated,
+            peak: 10_f64.powf(self.peak / 20.0),
+        }
+    }
+
+    /// The loudness of `tracks` played back to back, each weighted by its duration.
+    ///
+    /// This approximates measuring the whole album at once, which would gate quiet parts across tracks.
+    #[must_use]
+    pub fn album(tracks: &[(Self, Duration)]) -> Option<Self> {
+        let total: f64 = tracks.iter().map(|(_, d)| d.as_secs_f64()).sum();
+        if tracks.is_empty() || total <= 0.0 {
+            return None;
+        }
+
+        // loudness is logarithmic, so average the energy
+        let energy: f64 = tracks
+            .iter()
+            .map(|(l, d)| d.as_secs_f64() * 10_f64.powf(l.integrated / 10.0))
+            .sum();
+        let peak = tracks
+            .iter()
+            .map(|(l, _)| l.peak)
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        Some(Self {
+            integrated: 10.0 * (energy / total).log10(),
+            peak,
+        })
+    }
+
+    /// Parse the summary `ebur128` prints at the end of its output.
+    fn parse(output: &str) -> Option<Self> {
+        let summary = &output[output.rfind("Summary:")?..];
+
+        let mut integrated = None;
+        let mut peak = None;
+        for line in summary.lines().map(str::trim) {
+            // I:         -19.9 LUFS
+            if let Some(value) = line.strip_prefix("I:") {
+                integrated = value.split_whitespace().next()?.parse().ok();
+            // Peak:        0.4 dBFS
+            } else if let Some(value) = line.strip_prefix("Peak:") {
+                peak = value.split_whitespace().next()?.parse().ok();
+            }
+        }
+
+        Some(Self {
+            integrated: integrated?,
+            peak: peak?,
+        })
+    }
+}
+
+/// A `ReplayGain` adjustment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gain {
+    /// In dB.
+    pub gain: f64,
+    /// As a linear amplitude, where 1 is full scale.
+    pub peak: f64,
+}
+
+impl Gain {
+    /// The gain as written to tags, like `-6.54 dB`.
+    #[must_use]
+    pub fn gain_value(self) -> String {
+        format!("{:.2} dB", self.gain)
+    }
+
+    /// The peak as written to tags, like `0.988553`.
+    #[must_use]
+    pub fn peak_value(self) -> String {
+        format!("{:.6}", self.peak)
+    }
+}
+
+/// The `ReplayGain` of a track, and of its album if it was downloaded whole.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayGain {
+    pub track: Gain,
+    pub album: Option<Gain>,
+}
+
+/// Measure the loudness of the file at `path` with the ffmpeg at `ffmpeg`.
+///
+/// # Errors
+///
+/// - ffmpeg could not be run, or exited unsuccessfully.
+/// - ffmpeg did not print a loudness summary.
+pub async fn measure_loudness(ffmpeg: &Path, path: &Path) -> anyhow::Result<Loudness> {
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(path)
+        .args([
+            "-map",
+            "0:a:0",
+            "-af",
+            "ebur128=peak=true",
+            "-f",
+            "null",
+            "-",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|err| anyhow!("failed to run ffmpeg: {err}"))?;
+
+    // ebur128 logs to stderr
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        let last = stderr.lines().last().unwrap_or_default();
+        return Err(anyhow!("ffmpeg terminated with {}: {last}", output.status));
+    }
+
+    let loudness =
+        Loudness::parse(&stderr).ok_or_else(|| anyhow!("ffmpeg printed no loudness summary"))?;
+    debug!("measured {loudness:?}");
+
+    Ok(loudness)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Loudness;
+
+    #[test]
+    fn parse_summary() {
+        let output = "\
+[Parsed_ebur128_0 @ 0x5581] Summary:
+
+  Integrated loudness:
+    I:         -12.3 LUFS
+    Threshold: -22.6 LUFS
+
+  Loudness range:
+    LRA:         4.1 LU
+    Threshold: -32.4 LUFS
+    LRA low:   -15.0 LUFS
+    LRA high:  -10.9 LUFS
+
+  True peak:
+    Peak:        0.4 dBFS
+";
+        let loudness = Loudness::parse(output).unwrap();
+        assert_eq!(
+            loudness,
+            Loudness {
+                integrated: -12.3,
+                peak: 0.4
+            }
+        );
+
+        let gain = loudness.gain();
+        assert_eq!(gain.gain_value(), "-5.70 dB");
+        assert_eq!(gain.peak_value(), "1.047129");
+
+        assert_eq!(Loudness::parse("no summary"), None);
+    }
+
+    #[test]
+    fn album_loudness() {
+        let track =
+            |integrated, peak, secs| (Loudness { integrated, peak }, Duration::from_secs(secs));
+
+        let album = Loudness::album(&[track(-10.0, -1.0, 100), track(-10.0, -3.0, 200)]).unwrap();
+        assert!((album.integrated - -10.0).abs() < 1e-9);
+        assert!((album.peak - -1.0).abs() < 1e-9);
+
+        assert_eq!(Loudness::album(&[]), None);
+    }
+}
@@ -0,0 +1,260 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::{IterExt, spotify::Metadata};
+
+/// A value that can be used in a [`Template`], like `{title}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Var {
+    Title,
+    /// all artists
+    Artists,
+    /// the first artist
+    Artist,
+    /// all artists but the first
+    Featured,
+    Album,
+    AlbumArtists,
+    Year,
+    Date,
+    /// the year the album was first released, if it's a reissue
+    OriginalYear,
+    Track,
+    Disc,
+    Isrc,
+    Label,
+    SpotifyUrl,
+    /// where the file was downloaded from
+    Url,
+}
+
+impl Var {
+    const ALL: [Self; 15] = [
+        Self::Title,
+        Self::Artists,
+        Self::Artist,
+        Self::Featured,
+        Self::Album,
+        Self::AlbumArtists,
+        Self::Year,
+        Self::Date,
+        Self::OriginalYear,
+        Self::Track,
+        Self::Disc,
+        Self::Isrc,
+        Self::Label,
+        Self::SpotifyUrl,
+        Self::Url,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::Artists => "artists",
+            Self::Artist => "artist",
+            Self::Featured => "featured",
+            Self::Album => "album",
+            Self::AlbumArtists => "album_artists",
+            Self::Year => "year",
+            Self::Date => "date",
+            Self::OriginalYear => "original_year",
+            Self::Track => "track",
+            Self::Disc => "disc",
+            Self::Isrc => "isrc",
+            Self::Label => "label",
+            Self::SpotifyUrl => "spotify_url",
+            Self::Url => "url",
+        }
+    }
+
+    /// The value of `self` for `metadata`, empty if unknown.
+    fn value(self, metadata: &Metadata, url: Option<&str>) -> String {
+        let names = || metadata.artists.iter().map(|a| a.name.as_str());
+        match self {
+            Self::Title => metadata.name.clone(),
+            Self::Artists => names().join(", "),
+            Self::Artist => names().next().unwrap_or_default().to_string(),
+            Self::Featured => names().skip(1).join(", "),
+            Self::Album => metadata.album_name.clone().unwrap_or_default(),
+            Self::AlbumArtists => metadata.album_artists.iter().join(", "),
+            Self::Year => metadata
+                .release_date
+                .map(|date| date.year.to_string())
+                .unwrap_or_default(),
+            Self::Date => metadata
+                .release_date
+                .map(|date| date.to_string())
+                .unwrap_or_default(),
+            Self::OriginalYear => metadata
+                .original_year
+                .map(|year| year.to_string())
+                .unwrap_or_default(),
+            Self::Track => metadata.track_number.to_string(),
+            Self::Disc => metadata.disc_number.to_string(),
+            Self::Isrc => metadata.external_ids.isrc.clone().unwrap_or_default(),
+            Self::Label => metadata.label.clone().unwrap_or_default(),
+            Self::SpotifyUrl => format!("https://open.spotify.com/track/{}", metadata.spotify_id),
+            Self::Url => url.unwrap_or_default().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Var(Var),
+    /// Left out if any of its vars are empty.
+    Optional(Vec<Part>),
+}
+
+/// A tag value made from a track's metadata, like `{title} (feat. {featured})`.
+///
+/// A part in brackets with a variable, like `[ (feat. {featured})]`, is left out if any of its variables are empty.
+/// Brackets without variables are kept as they are.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        // the parts of the current bracket, and whether it has a var
+        let mut optional: Option<(Vec<Part>, bool)> = None;
+        let mut text = String::new();
+
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                    let var = Var::ALL
+                        .into_iter()
+                        .find(|v| v.name() == name)
+                        .ok_or_else(|| {
+                            let names = Var::ALL.iter().map(|v| v.name()).join(", ");
+                            format!(
+                                "unknown template variable `{{{name}}}`, expected one of {names}"
+                            )
+                        })?;
+
+                    let current = match optional.as_mut() {
+                        Some((inner, has_var)) => {
+                            *has_var = true;
+                            inner
+                        }
+                        None => &mut parts,
+                    };
+                    if !text.is_empty() {
+                        current.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    current.push(Part::Var(var));
+                }
+                '[' if optional.is_none() => {
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    optional = Some((Vec::new(), false));
+                }
+                ']' if optional.is_some() => {
+                    let (mut inner, has_var) = optional.take().expect("is some");
+                    if has_var {
+                        if !text.is_empty() {
+                            inner.push(Part::Text(std::mem::take(&mut text)));
+                        }
+                        parts.push(Part::Optional(inner));
+                    } else {
+                        // only text, so not optional
+                        text = format!("[{text}]");
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+
+        if optional.is_some() {
+            return Err("unclosed `[` in template".to_string());
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Self { parts })
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = String;
+
+    fn try_from(template: String) -> Result<Self, Self::Error> {
+        template.parse()
+    }
+}
+
+impl Template {
+    /// Fill in the template with `metadata`, and `url` (where the file was downloaded from).
+    #[must_use]
+    pub fn render(&self, metadata: &Metadata, url: Option<&str>) -> String {
+        let mut rendered = String::new();
+        render_parts(&self.parts, metadata, url, &mut rendered);
+        rendered
+    }
+}
+
+/// Returns whether every var of `parts` had a value.
+fn render_parts(parts: &[Part], metadata: &Metadata, url: Option<&str>, out: &mut String) -> bool {
+    let mut complete = true;
+    for part in parts {
+        match part {
+            Part::Text(text) => out.push_str(text),
+            Part::Var(var) => {
+                let value = var.value(metadata, url);
+                complete &= !value.is_empty();
+                out.push_str(&value);
+            }
+            Part::Optional(inner) => {
+                let mut optional = String::new();
+                if render_parts(inner, metadata, url, &mut optional) {
+                    out.push_str(&optional);
+                }
+            }
+        }
+    }
+    complete
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spotify::{Metadata, search::SpotifyArtist};
+
+    use super::Template;
+
+    fn artist(name: &str) -> SpotifyArtist {
+        SpotifyArtist {
+            name: name.to_string(),
+            genres: Vec::new(),
+            id: String::new(),
+        }
+    }
+
+    #[test]
+    fn render() {
+        let template: Template = "{title}[ (feat. {featured})] [Live]".parse().unwrap();
+        let mut metadata = Metadata {
+            name: "Song".to_string(),
+            artists: vec![artist("A")],
+            ..Default::default()
+        };
+        assert_eq!(template.render(&metadata, None), "Song [Live]");
+
+        metadata.artists.extend([artist("B"), artist("C")]);
+        assert_eq!(template.render(&metadata, None), "Song (feat. B, C) [Live]");
+
+        assert!("{nope}".parse::<Template>().is_err());
+        assert!("[{title}".parse::<Template>().is_err());
+    }
+}
@@ -0,0 +1,40 @@
+use lofty::{
+    ogg::{OggPictureStorage, VorbisComments},
+    picture::Picture,
+};
+use tracing::warn;
+
+use super::{Field, TagReader, TagWriter};
+
+impl TagWriter for VorbisComments {
+    // vorbis comments repeat the field for each value
+    fn set_values(&mut self, field: Field, values: Vec<String>) {
+        let key = field.name();
+
+        self.remove(key).for_each(drop);
+        for value in values {
+            self.push(key.to_string(), value);
+        }
+    }
+
+    fn set_explicit(&mut self) {
+        // 1 is explicit
+        self.insert("ITUNESADVISORY".to_string(), "1".to_string());
+    }
+
+    fn set_lyrics(&mut self, lyrics: String) {
+        self.insert("LYRICS".to_string(), lyrics);
+    }
+
+    fn set_cover(&mut self, cover: Picture) {
+        if let Err(err) = self.insert_picture(cover, None) {
+            warn!("failed to add cover: {err}");
+        }
+    }
+}
+
+impl TagReader for VorbisComments {
+    fn custom_value(&self, field: Field) -> Option<String> {
+        self.get(field.name()).map(ToString::to_string)
+    }
+}
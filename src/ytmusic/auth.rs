@@ -1,48 +1,353 @@
-use chrono::Utc;
+use anyhow::{Context, anyhow};
+use chrono::{DateTime, Utc};
+use reqwest::{RequestBuilder, StatusCode};
+use serde_json::Value;
 use sha1::{Digest, Sha1};
 
+use super::search::{base_context, base_headers};
+use crate::CLIENT;
+
+/// Lists the accounts of the logged in user.
+const ACCOUNT_MENU_API: &str = "https://music.youtube.com/youtubei/v1/account/account_menu";
+
+/// Authorizes ytmusic requests with the user's cookies, signed with the "__Secure-3PAPISID" one.
+#[derive(Debug, Clone)]
 pub struct Browser {
-    auth: String,
+    sapisid: String,
+    /// every youtube cookie, as a Cookie header value
+    cookie: String,
 }
 
 impl Browser {
-    /// Takes a "__Secure-3PAPISID" cookie and converts into the corresponding authorization header value.
+    /// Authorize with the cookies in `raw_cookie`, see [`read_cookies`].
+    ///
+    /// # Errors
+    ///
+    /// See [`read_cookies`].
+    pub fn from_cookie(raw_cookie: &str) -> anyhow::Result<Self> {
+        let (cookies, sapisid) = read_cookies(raw_cookie)?;
+        Ok(Self {
+            sapisid,
+            cookie: join_cookies(&cookies),
+        })
+    }
+
+    /// Send the cookies with `req`, and sign it for the logged in account.
+    ///
+    /// <https://github.com/sigma67/ytmusicapi/blob/14a575e1685c21474e03461cbcccc1bdff44b47e/ytmusicapi/ytmusic.py#L169>
+    pub fn authorize(&self, req: RequestBuilder) -> RequestBuilder {
+        req.header("Authorization", self.header())
+            .header("X-Goog-AuthUser", "0")
+            .header("Cookie", &self.cookie)
+    }
+
+    /// The authorization header value for a request sent now.
+    ///
+    /// Recomputed every time, since ytmusic rejects stale timestamps.
     ///
     /// <https://github.com/sigma67/ytmusicapi/blob/21445ca6f3bff83fc4f4f4546fc316710f517731/ytmusicapi/helpers.py#L60>
     #[must_use]
-    pub fn new(sapisid: &str) -> Self {
+    pub fn header(&self) -> String {
         let timestamp = Utc::now().timestamp();
 
-        let hash = Sha1::digest(format!("{timestamp} {sapisid} https://music.youtube.com"));
+        let hash = Sha1::digest(format!(
+            "{timestamp} {} https://music.youtube.com",
+            self.sapisid
+        ));
 
-        Self {
-            auth: format!("SAPISIDHASH {timestamp}_{hash:x}"),
-        }
+        format!("SAPISIDHASH {timestamp}_{hash:x}")
     }
+}
 
-    #[must_use]
-    pub fn into_inner(self) -> String {
-        self.auth
+/// The cookie ytmusic requests are authorized with.
+const SAPISID: &str = "__Secure-3PAPISID";
+
+#[derive(Debug, PartialEq, Eq)]
+struct Cookie {
+    name: String,
+    value: String,
+    /// only known for cookies from a `cookies.txt`
+    expires: Option<DateTime<Utc>>,
+}
+
+/// Read the youtube cookies in `input`, which can be:
+///
+/// - a Netscape `cookies.txt`, like the one given to yt-dlp,
+/// - a ytmusicapi `browser.json`,
+/// - copied request headers (or just the Cookie header),
+/// - a bare cookie, like `a=b; c=d`.
+///
+/// # Errors
+///
+/// - `input` looks like json, but is not a `browser.json`.
+/// - There is no `__Secure-3PAPISID` cookie, or it expired.
+///
+/// Returns the cookies and the value of `__Secure-3PAPISID`.
+fn read_cookies(input: &str) -> anyhow::Result<(Vec<Cookie>, String)> {
+    let input = input.trim();
+    let cookies = if input.starts_with('{') {
+        split_cookies(&browser_json_cookie(input)?)
+    } else if input.lines().any(|l| l.split('\t').count() == 7) {
+        netscape_cookies(input)
+    } else {
+        split_cookies(header_cookie(input).unwrap_or(input))
+    };
+
+    let sapisid = cookies.iter().find(|c| c.name == SAPISID).ok_or_else(|| {
+        anyhow!("found no {SAPISID} cookie: make sure you are logged in to music.youtube.com")
+    })?;
+    if let Some(expires) = sapisid.expires
+        && expires <= Utc::now()
+    {
+        return Err(anyhow!(
+            "the {SAPISID} cookie expired at {expires}: log in to music.youtube.com and export the cookies again"
+        ));
     }
+
+    let sapisid = sapisid.value.clone();
+    Ok((cookies, sapisid))
+}
+
+/// The `cookie` header of a ytmusicapi `browser.json`.
+fn browser_json_cookie(input: &str) -> anyhow::Result<String> {
+    let headers: serde_json::Map<String, Value> =
+        serde_json::from_str(input).context("parsing browser.json")?;
+    headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("cookie"))
+        .and_then(|(_, value)| value.as_str())
+        .map(ToString::to_string)
+        .ok_or_else(|| anyhow!("browser.json has no cookie header"))
+}
+
+/// The youtube cookies of a Netscape `cookies.txt`.
+///
+/// <https://curl.se/docs/http-cookies.html#cookie-file-format>
+fn netscape_cookies(input: &str) -> Vec<Cookie> {
+    input
+        .lines()
+        .map(|l| l.strip_prefix("#HttpOnly_").unwrap_or(l))
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| {
+            let [domain, _, _, _, expires, name, value] =
+                l.trim_end_matches('\r').split('\t').collect::<Vec<_>>()[..]
+            else {
+                return None;
+            };
+            if !domain.trim_start_matches('.').ends_with("youtube.com") {
+                return None;
+            }
+            Some(Cookie {
+                name: name.to_string(),
+                value: value.to_string(),
+                // 0 is a session cookie
+                expires: expires
+                    .parse()
+                    .ok()
+                    .filter(|&expires| expires != 0)
+                    .and_then(|expires| DateTime::from_timestamp(expires, 0)),
+            })
+        })
+        .collect()
 }
 
-impl AsRef<str> for Browser {
-    fn as_ref(&self) -> &str {
-        &self.auth
+/// The value of the Cookie header in copied request headers.
+///
+/// Headers can be copied as `Cookie: value` lines, as `cookie:` and the value on the next line,
+/// quoted (`"cookie": "value",`), or as curl arguments (`-H 'cookie: value'`).
+fn header_cookie(input: &str) -> Option<&str> {
+    fn unquote(s: &str) -> &str {
+        s.trim().trim_matches(['"', '\'', ',', '\\', ' '])
+    }
+
+    let mut lines = input
+        .lines()
+        .map(|l| unquote(l.trim().strip_prefix("-H ").unwrap_or(l)));
+
+    while let Some(line) = lines.next() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        if !unquote(name).eq_ignore_ascii_case("cookie") {
+            continue;
+        }
+        let value = unquote(value);
+        return if value.is_empty() {
+            lines.next()
+        } else {
+            Some(value)
+        };
     }
+    None
 }
 
-/// Takes a list of headers (or just the Cookie header) and finds the "__Secure-3PAPISID" cookie.
-#[must_use]
-pub fn parse_cookie(input: &str) -> Option<&str> {
-    let cookies = if input.starts_with("Cookie: ") {
-        input
-    } else {
-        input.lines().find(|l| l.starts_with("Cookie: "))?
-    };
-    let cookie = cookies
+/// Split a Cookie header value into its cookies.
+fn split_cookies(cookies: &str) -> Vec<Cookie> {
+    cookies
         .split(';')
-        .find(|cookie| cookie.trim_ascii_start().starts_with("__Secure-3PAPISID"))?;
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .map(|(name, value)| Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            expires: None,
+        })
+        .collect()
+}
+
+/// Join `cookies` into a Cookie header value.
+fn join_cookies(cookies: &[Cookie]) -> String {
+    cookies
+        .iter()
+        .map(|c| format!("{}={}", c.name, c.value))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Import the youtube cookies in `input` (see [`read_cookies`]) as a `Cookie: ` header line.
+///
+/// # Errors
+///
+/// See [`read_cookies`].
+pub fn import_cookie(input: &str) -> anyhow::Result<String> {
+    let (cookies, _) = read_cookies(input)?;
+    Ok(format!("Cookie: {}", join_cookies(&cookies)))
+}
+
+/// Find the `__Secure-3PAPISID` cookie in `input` (see [`read_cookies`]).
+///
+/// # Errors
+///
+/// See [`read_cookies`].
+pub fn parse_cookie(input: &str) -> anyhow::Result<String> {
+    Ok(read_cookies(input)?.1)
+}
+
+/// Check that `raw_cookie` (see [`parse_cookie`]) is logged in to ytmusic, by asking for its account menu.
+///
+/// Returns the name of the account.
+///
+/// # Errors
+///
+/// - The cookie has no `__Secure-3PAPISID`, or it expired.
+/// - The request could not be sent, or was refused.
+/// - ytmusic did not recognize the cookie as logged in.
+pub async fn check_cookie(raw_cookie: &str) -> anyhow::Result<String> {
+    let auth = Browser::from_cookie(raw_cookie)?;
+
+    let req = CLIENT
+        .post(ACCOUNT_MENU_API)
+        .query(&[("alt", "json")])
+        .json(&base_context())
+        .headers(base_headers());
+    let resp = auth.authorize(req).send().await?;
+
+    if matches!(
+        resp.status(),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+    ) {
+        return Err(anyhow!(
+            "ytmusic refused the cookie with {}: it may have expired, log in to music.youtube.com and export it again",
+            resp.status()
+        ));
+    }
+    if !resp.status().is_success() {
+        return Err(anyhow!(
+            "ytmusic refused the account request with {}",
+            resp.status()
+        ));
+    }
 
-    cookie.split('=').nth(1)
+    let menu: Value = resp.json().await.context("parsing the account menu")?;
+    account_name(&menu).ok_or_else(|| {
+        anyhow!(
+            "ytmusic did not recognize the cookie as logged in: it may have expired, log in to music.youtube.com and export it again"
+        )
+    })
+}
+
+/// The name of the logged in account in an account menu response, which anonymous requests don't get.
+fn account_name(menu: &Value) -> Option<String> {
+    menu.pointer("/actions/0/openPopupAction/popup/multiPageMenuRenderer/header/activeAccountHeaderRenderer/accountName/runs/0/text")
+        .and_then(Value::as_str)
+        .map(ToString::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{account_name, import_cookie, parse_cookie};
+
+    #[test]
+    fn cookie_formats() {
+        let headers =
+            "Host: music.youtube.com\nCookie: SID=a=b; __Secure-3PAPISID=xyz/1\nX-Goog-AuthUser: 0";
+        assert_eq!(parse_cookie(headers).unwrap(), "xyz/1");
+        assert_eq!(
+            import_cookie(headers).unwrap(),
+            "Cookie: SID=a=b; __Secure-3PAPISID=xyz/1"
+        );
+
+        let copied = "accept: */*\ncookie:\nSID=a; __Secure-3PAPISID=xyz\n";
+        assert_eq!(parse_cookie(copied).unwrap(), "xyz");
+        assert_eq!(
+            parse_cookie("  \"cookie: __Secure-3PAPISID=xyz\",").unwrap(),
+            "xyz"
+        );
+        assert_eq!(parse_cookie("SID=a; __Secure-3PAPISID=xyz").unwrap(), "xyz");
+
+        let browser_json =
+            r#"{"Accept": "*/*", "Cookie": "__Secure-3PAPISID=xyz", "X-Goog-AuthUser": "0"}"#;
+        assert_eq!(parse_cookie(browser_json).unwrap(), "xyz");
+        assert!(parse_cookie(r#"{"accept": "*/*"}"#).is_err());
+
+        let cookies_txt = "# Netscape HTTP Cookie File\n\
+            .google.com\tTRUE\t/\tTRUE\t4102444800\t__Secure-3PAPISID\tgoogle\n\
+            #HttpOnly_.youtube.com\tTRUE\t/\tTRUE\t0\tSID\ta\n\
+            .youtube.com\tTRUE\t/\tTRUE\t4102444800\t__Secure-3PAPISID\txyz\n";
+        assert_eq!(
+            import_cookie(cookies_txt).unwrap(),
+            "Cookie: SID=a; __Secure-3PAPISID=xyz"
+        );
+
+        let expired = ".youtube.com\tTRUE\t/\tTRUE\t946684800\t__Secure-3PAPISID\txyz";
+        assert!(
+            parse_cookie(expired)
+                .unwrap_err()
+                .to_string()
+                .contains("expired")
+        );
+        assert!(
+            parse_cookie("Cookie: SID=a")
+                .unwrap_err()
+                .to_string()
+                .contains("found no")
+        );
+    }
+
+    #[test]
+    fn account_names() {
+        let menu = json!({
+            "actions": [{
+                "openPopupAction": {
+                    "popup": {
+                        "multiPageMenuRenderer": {
+                            "header": {
+                                "activeAccountHeaderRenderer": {
+                                    "accountName": { "runs": [{ "text": "someone" }] }
+                                }
+                            }
+                        }
+                    }
+                }
+            }]
+        });
+        assert_eq!(account_name(&menu).as_deref(), Some("someone"));
+
+        // logged out, the menu only offers to sign in
+        let menu = json!({ "actions": [{ "openPopupAction": { "popup": {
+            "multiPageMenuRenderer": { "sections": [] }
+        } } }] });
+        assert_eq!(account_name(&menu), None);
+    }
 }
@@ -7,18 +7,17 @@ use std::{sync::OnceLock, time::Instant};
 use anyhow::anyhow;
 use chrono::{Datelike, Utc};
 use regex::Regex;
-use reqwest::{Response, header::HeaderMap};
+use reqwest::{RequestBuilder, Response, header::HeaderMap};
 use serde_json::{Value, json};
 use tokio::sync::OnceCell;
 use tracing::{debug, trace};
 
-use crate::CLIENT;
+use super::auth::Browser;
+use crate::{CLIENT, network};
 
 const SEARCH_API: &str = "https://music.youtube.com/youtubei/v1/search";
-const USER_AGENT: &str =
-    "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:88.0) Gecko/20100101 Firefox/88.0";
 
-/// Search youtube music by `query`, using authentication `auth`.
+/// Search youtube music by `query`, authorized by `auth`, or anonymously with just the visitor id.
 ///
 /// # Errors
 ///
@@ -30,7 +29,7 @@ const USER_AGENT: &str =
 pub async fn search(
     query: impl AsRef<str>,
     filter: Option<SearchFilter>,
-    auth: &str,
+    auth: Option<&Browser>,
 ) -> anyhow::Result<Response> {
     let query = query.as_ref();
 
@@ -78,23 +77,36 @@ pub async fn search(
     static VISITOR_ID: OnceLock<String> = OnceLock::new();
     let visitor_id = VISITOR_ID.get_or_init(|| parse_visitor_id(base_resp).unwrap());
 
-    let resp = CLIENT
+    let resp = search_request(&body, auth, cookies, visitor_id)
+        .send()
+        .await?;
+
+    Ok(resp)
+}
+
+/// The search request with the json `body`: with the user's cookies if there's `auth`, or else
+/// with the anonymous `cookies`.
+fn search_request(
+    body: &Value,
+    auth: Option<&Browser>,
+    cookies: &str,
+    visitor_id: &str,
+) -> RequestBuilder {
+    let req = CLIENT
         .post(SEARCH_API)
-        .json(&body)
-        // https://github.com/sigma67/ytmusicapi//blob/14a575e1685c21474e03461cbcccc1bdff44b47e/ytmusicapi/ytmusic.py#L169
-        .header("Authentication", auth)
+        .json(body)
         // https://github.com/sigma67/ytmusicapi//blob/fe95f5974efd7ba8b87ba030a1f528afe41a5a31/ytmusicapi/constants.py#L3
         .query(&[("alt", "json")])
         .headers(base_headers())
-        .header("Cookie", cookies)
-        // https://github.com/sigma67/ytmusicapi//blob/14a575e1685c21474e03461cbcccc1bdff44b47e/ytmusicapi/ytmusic.py#L164
-        .header("X-Goog-Visitor-Id", visitor_id)
         // // https://github.com/sigma67/ytmusicapi//blob/14a575e1685c21474e03461cbcccc1bdff44b47e/ytmusicapi/ytmusic.py#L180
         // .header("X-Goog-Request-Time", Utc::now().timestamp().to_string())
-        .send()
-        .await?;
+        // https://github.com/sigma67/ytmusicapi//blob/14a575e1685c21474e03461cbcccc1bdff44b47e/ytmusicapi/ytmusic.py#L164
+        .header("X-Goog-Visitor-Id", visitor_id);
 
-    Ok(resp)
+    match auth {
+        Some(auth) => auth.authorize(req),
+        None => req.header("Cookie", cookies),
+    }
 }
 
 // https://github.com/sigma67/ytmusicapi/blob/21445ca6f3bff83fc4f4f4546fc316710f517731/ytmusicapi/mixins/search.py#L146
@@ -124,7 +136,7 @@ impl SearchFilter {
 ///
 /// <https://github.com/sigma67/ytmusicapi//blob/a979691bb03c1cb5e7e39985bbd4014187940d68/ytmusicapi/helpers.py#L30>
 #[inline]
-fn base_context() -> Value {
+pub(crate) fn base_context() -> Value {
     let now = Utc::now();
     // time.strftime("%Y%m%d", time.gmtime())
     let now = format!("{}{:02}{:02}", now.year(), now.month(), now.day());
@@ -146,7 +158,7 @@ fn base_context() -> Value {
 ///
 /// <https://github.com/sigma67/ytmusicapi//blob/a979691bb03c1cb5e7e39985bbd4014187940d68/ytmusicapi/helpers.py#L17>
 #[inline]
-fn base_headers() -> HeaderMap {
+pub(crate) fn base_headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
 
     headers.insert("Accept", "*/*".parse().unwrap());
@@ -155,7 +167,7 @@ fn base_headers() -> HeaderMap {
     headers.insert("Origin", "https://music.youtube.com".parse().unwrap());
     headers.insert("X-Origin", "https://music.youtube.com".parse().unwrap());
     headers.insert("Referer", "https://music.youtube.com".parse().unwrap());
-    headers.insert("User-Agent", USER_AGENT.parse().unwrap());
+    headers.insert("User-Agent", network::user_agent().parse().unwrap());
 
     headers
 }
@@ -212,3 +224,34 @@ fn parse_visitor_id(resp: &str) -> anyhow::Result<String> {
         .ok_or(anyhow!("VISITOR_DATA not str"))?
         .to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::search_request;
+    use crate::ytmusic::auth::Browser;
+
+    #[test]
+    fn search_cookies() {
+        let auth = Browser::from_cookie("Cookie: SID=a; __Secure-3PAPISID=xyz").unwrap();
+        let req = search_request(&json!({}), Some(&auth), "SOCS=CAI", "visitor")
+            .build()
+            .unwrap();
+        let headers = req.headers();
+        assert_eq!(headers["Cookie"], "SID=a; __Secure-3PAPISID=xyz");
+        assert!(
+            headers["Authorization"]
+                .to_str()
+                .unwrap()
+                .starts_with("SAPISIDHASH ")
+        );
+        assert_eq!(headers["X-Goog-AuthUser"], "0");
+
+        let req = search_request(&json!({}), None, "SOCS=CAI", "visitor")
+            .build()
+            .unwrap();
+        assert_eq!(req.headers()["Cookie"], "SOCS=CAI");
+        assert!(!req.headers().contains_key("Authorization"));
+    }
+}